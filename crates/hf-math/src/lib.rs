@@ -0,0 +1,478 @@
+#![cfg_attr(not(test), no_std)]
+
+use core::fmt;
+use core::str::FromStr;
+
+use ethereum_types::U256;
+
+/* Number of fractional bits of a Q64.64 number. */
+pub const FRAC_BITS: u32 = 64;
+/* Number of fractional bits of a Kamino `Fraction` (U68F60, stored as `*_sf: u128`). */
+pub const KAMINO_FRACTION_BITS: u32 = 60;
+
+const FRAC_MASK: u128 = (1u128 << FRAC_BITS) - 1;
+const BPS_DENOMINATOR: u128 = 10_000;
+
+// --------------- Errors ---------------
+
+/* Arithmetic failure of a checked operation. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathError {
+    Overflow,
+    DivisionByZero,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathError::Overflow => f.write_str("math overflow"),
+            MathError::DivisionByZero => f.write_str("division by zero"),
+        }
+    }
+}
+
+impl core::error::Error for MathError {}
+
+/* Failure to parse a decimal string into a `Q64`. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseQ64Error {
+    Empty,
+    InvalidDigit,
+    Overflow,
+}
+
+impl fmt::Display for ParseQ64Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseQ64Error::Empty => f.write_str("cannot parse Q64 from empty string"),
+            ParseQ64Error::InvalidDigit => f.write_str("invalid digit found in string"),
+            ParseQ64Error::Overflow => f.write_str("number too large to fit in Q64.64"),
+        }
+    }
+}
+
+impl core::error::Error for ParseQ64Error {}
+
+// --------------- Rounding ---------------
+
+/* Direction in which an inexact result is rounded. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Toward zero (all values are unsigned).
+    Floor,
+    /// Away from zero.
+    Ceil,
+    /// To the nearest value, ties rounded up.
+    Nearest,
+}
+
+/* Calculates 10^exp, failing above 10^38 (the largest power of ten in a u128). */
+#[inline(always)]
+pub fn ten_pow(exp: u8) -> Result<u128, MathError> {
+    10u128.checked_pow(exp as u32).ok_or(MathError::Overflow)
+}
+
+/* Computes a * b / denom with a 256-bit intermediate and the given rounding. */
+#[inline(never)]
+pub fn mul_div(a: u128, b: u128, denom: u128, rounding: Rounding) -> Result<u128, MathError> {
+    if denom == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    div_rounded(U256::from(a) * U256::from(b), U256::from(denom), rounding)
+}
+
+fn div_rounded(num: U256, denom: U256, rounding: Rounding) -> Result<u128, MathError> {
+    let (quotient, remainder) = num.div_mod(denom);
+    let round_up = match rounding {
+        Rounding::Floor => false,
+        Rounding::Ceil => !remainder.is_zero(),
+        Rounding::Nearest => remainder >= denom - remainder,
+    };
+    let result = if round_up { quotient + 1 } else { quotient };
+    if result > U256::from(u128::MAX) {
+        return Err(MathError::Overflow);
+    }
+
+    Ok(result.as_u128())
+}
+
+// --------------- Q64 ---------------
+
+/* Unsigned Q64.64 fixed-point number: 64 integer bits and 64 fractional bits. */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Q64(u128);
+
+impl Q64 {
+    pub const ZERO: Q64 = Q64(0);
+    pub const ONE: Q64 = Q64(1u128 << FRAC_BITS);
+    pub const MAX: Q64 = Q64(u128::MAX);
+
+    /* Wraps raw Q64.64 bits. */
+    #[inline(always)]
+    pub const fn from_bits(bits: u128) -> Self {
+        Q64(bits)
+    }
+
+    /* Returns the raw Q64.64 bits. */
+    #[inline(always)]
+    pub const fn to_bits(self) -> u128 {
+        self.0
+    }
+
+    /* Converts an integer. */
+    #[inline(always)]
+    pub const fn from_int(value: u64) -> Self {
+        Q64((value as u128) << FRAC_BITS)
+    }
+
+    /* Converts num / denom. */
+    pub fn from_ratio(num: u128, denom: u128, rounding: Rounding) -> Result<Self, MathError> {
+        mul_div(num, Self::ONE.0, denom, rounding).map(Q64)
+    }
+
+    /* Converts basis points (10_000 bps = 1.0). */
+    pub fn from_bps(bps: u16, rounding: Rounding) -> Self {
+        // bps * 2^64 / 10_000 always fits in a u128
+        Q64(mul_div(bps as u128, Self::ONE.0, BPS_DENOMINATOR, rounding).unwrap_or(u128::MAX))
+    }
+
+    /* Converts mantissa * 10^expo (e.g. an oracle price and its exponent). */
+    pub fn from_decimal(mantissa: u128, expo: i32, rounding: Rounding) -> Result<Self, MathError> {
+        let scale = ten_pow(u8::try_from(expo.unsigned_abs()).map_err(|_| MathError::Overflow)?)?;
+        if expo < 0 {
+            Self::from_ratio(mantissa, scale, rounding)
+        } else {
+            mantissa
+                .checked_mul(scale)
+                .and_then(|v| v.checked_mul(Self::ONE.0))
+                .map(Q64)
+                .ok_or(MathError::Overflow)
+        }
+    }
+
+    /* Converts a Kamino 2^60-scaled `Fraction` (e.g. `market_price_sf`); exact when it fits. */
+    pub fn from_fraction_sf(value_sf: u128) -> Result<Self, MathError> {
+        value_sf
+            .checked_mul(1u128 << (FRAC_BITS - KAMINO_FRACTION_BITS))
+            .map(Q64)
+            .ok_or(MathError::Overflow)
+    }
+
+    /* Converts to a Kamino 2^60-scaled `Fraction`, dropping the 4 extra fractional bits. */
+    pub fn to_fraction_sf(self, rounding: Rounding) -> u128 {
+        let shift = FRAC_BITS - KAMINO_FRACTION_BITS;
+        // cannot overflow: the result is at most u128::MAX >> 4 (+1)
+        div_rounded(U256::from(self.0), U256::from(1u128 << shift), rounding).unwrap_or(u128::MAX)
+    }
+
+    /* Integer part, rounded as requested. */
+    pub fn to_int(self, rounding: Rounding) -> u128 {
+        div_rounded(U256::from(self.0), U256::from(Self::ONE.0), rounding).unwrap_or(u128::MAX)
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Result<Self, MathError> {
+        self.0.checked_add(rhs.0).map(Q64).ok_or(MathError::Overflow)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Result<Self, MathError> {
+        self.0.checked_sub(rhs.0).map(Q64).ok_or(MathError::Overflow)
+    }
+
+    pub fn checked_mul(self, rhs: Self, rounding: Rounding) -> Result<Self, MathError> {
+        mul_div(self.0, rhs.0, Self::ONE.0, rounding).map(Q64)
+    }
+
+    pub fn checked_div(self, rhs: Self, rounding: Rounding) -> Result<Self, MathError> {
+        mul_div(self.0, Self::ONE.0, rhs.0, rounding).map(Q64)
+    }
+
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Q64(self.0.saturating_add(rhs.0))
+    }
+
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Q64(self.0.saturating_sub(rhs.0))
+    }
+
+    /* Saturates at `Q64::MAX` on overflow. */
+    pub fn saturating_mul(self, rhs: Self, rounding: Rounding) -> Self {
+        self.checked_mul(rhs, rounding).unwrap_or(Self::MAX)
+    }
+
+    /* Saturates at `Q64::MAX` on overflow or division by zero. */
+    pub fn saturating_div(self, rhs: Self, rounding: Rounding) -> Self {
+        self.checked_div(rhs, rounding).unwrap_or(Self::MAX)
+    }
+}
+
+/* Formats as an exact decimal string (a Q64.64 value has at most 64 fractional digits).
+- With a precision (`{:.6}`), fractional digits beyond it are truncated. */
+impl fmt::Display for Q64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0 >> FRAC_BITS)?;
+
+        let mut frac = self.0 & FRAC_MASK;
+        let digits = match f.precision() {
+            Some(precision) => precision,
+            None if frac == 0 => return Ok(()),
+            None => usize::MAX,
+        };
+        if digits == 0 {
+            return Ok(());
+        }
+
+        f.write_str(".")?;
+        let mut written = 0;
+        while written < digits && (frac != 0 || f.precision().is_some()) {
+            frac *= 10;
+            let digit = (frac >> FRAC_BITS) as u8;
+            frac &= FRAC_MASK;
+            fmt::Write::write_char(f, (b'0' + digit) as char)?;
+            written += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/* Parses a decimal string such as `"1.25"`, rounding the fraction down to the nearest 2^-64.
+- Round-trips exactly with `Display`. */
+impl FromStr for Q64 {
+    type Err = ParseQ64Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (int_part, frac_part) = s.split_once('.').unwrap_or((s, ""));
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(ParseQ64Error::Empty);
+        }
+
+        let mut int: u64 = 0;
+        for b in int_part.bytes() {
+            let digit = decimal_digit(b)?;
+            int = int
+                .checked_mul(10)
+                .and_then(|v| v.checked_add(digit as u64))
+                .ok_or(ParseQ64Error::Overflow)?;
+        }
+
+        // Horner's scheme from the last digit: floor((d + floor(x)) / 10) == floor((d + x) / 10),
+        // so flooring at every step yields the exact floor of the whole fraction.
+        let mut frac: u128 = 0;
+        for b in frac_part.bytes().rev() {
+            let digit = decimal_digit(b)?;
+            frac = (((digit as u128) << FRAC_BITS) + frac) / 10;
+        }
+
+        Ok(Q64(((int as u128) << FRAC_BITS) | frac))
+    }
+}
+
+#[inline(always)]
+fn decimal_digit(b: u8) -> Result<u8, ParseQ64Error> {
+    if b.is_ascii_digit() {
+        Ok(b - b'0')
+    } else {
+        Err(ParseQ64Error::InvalidDigit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE: u128 = 1u128 << 64;
+    // Reference values computed with arbitrary-precision integers: floor(x * 2^64).
+    const PI_Q64: Q64 = Q64::from_bits(57_952_155_664_616_982_739);
+    const E_Q64: Q64 = Q64::from_bits(50_143_449_209_799_256_682);
+
+    #[test]
+    fn mul_div_vectors() {
+        assert_eq!(mul_div(1, ONE, 1, Rounding::Floor), Ok(ONE));
+        assert_eq!(mul_div(1_000_000, ONE, 1_000_000, Rounding::Floor), Ok(ONE));
+        assert_eq!(mul_div(3, ONE, 10, Rounding::Floor), Ok(5_534_023_222_112_865_484));
+        assert_eq!(mul_div(0, u128::MAX, 1, Rounding::Floor), Ok(0));
+        // the 256-bit intermediate keeps u128::MAX * u128::MAX exact
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, Rounding::Floor), Ok(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, 1, 1, Rounding::Floor), Ok(u128::MAX));
+    }
+
+    #[test]
+    fn mul_div_rounding() {
+        assert_eq!(mul_div(3, ONE, 10, Rounding::Ceil), Ok(5_534_023_222_112_865_485));
+        assert_eq!(mul_div(3, ONE, 10, Rounding::Nearest), Ok(5_534_023_222_112_865_485));
+        assert_eq!(mul_div(1, ONE, 3, Rounding::Nearest), Ok(6_148_914_691_236_517_205));
+        // exact results are never adjusted
+        assert_eq!(mul_div(5, 4, 2, Rounding::Ceil), Ok(10));
+        // ties round up
+        assert_eq!(mul_div(1, 1, 2, Rounding::Nearest), Ok(1));
+        assert_eq!(mul_div(1, 1, 2, Rounding::Floor), Ok(0));
+    }
+
+    #[test]
+    fn mul_div_rejects_zero_denominator_and_overflow() {
+        assert_eq!(mul_div(1, 1, 0, Rounding::Floor), Err(MathError::DivisionByZero));
+        assert_eq!(mul_div(u128::MAX, 2, 1, Rounding::Floor), Err(MathError::Overflow));
+        assert_eq!(
+            mul_div(u128::MAX, u128::MAX, u128::MAX - 1, Rounding::Floor),
+            Err(MathError::Overflow)
+        );
+        // rounding up past u128::MAX overflows too
+        assert_eq!(mul_div(u128::MAX, 3, 2, Rounding::Ceil), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn checked_mul_vectors() {
+        assert_eq!(Q64::ONE.checked_mul(Q64::ONE, Rounding::Floor), Ok(Q64::ONE));
+        // 1.5 * 2.5 = 3.75
+        assert_eq!(
+            Q64::from_bits(3 * ONE / 2).checked_mul(Q64::from_bits(5 * ONE / 2), Rounding::Floor),
+            Ok(Q64::from_bits(15 * ONE / 4))
+        );
+        assert_eq!(
+            PI_Q64.checked_mul(E_Q64, Rounding::Floor),
+            Ok(Q64::from_bits(157_530_291_663_158_267_693))
+        );
+        // products below 2^-64 truncate to zero, or round up to one ulp
+        assert_eq!(Q64::from_bits(1).checked_mul(Q64::from_bits(1), Rounding::Floor), Ok(Q64::ZERO));
+        assert_eq!(Q64::from_bits(1).checked_mul(Q64::from_bits(1), Rounding::Ceil), Ok(Q64::from_bits(1)));
+        assert_eq!(Q64::MAX.checked_mul(Q64::ONE, Rounding::Floor), Ok(Q64::MAX));
+        assert_eq!(Q64::MAX.checked_mul(Q64::from_bits(1), Rounding::Floor), Ok(Q64::from_bits(u128::MAX >> 64)));
+    }
+
+    #[test]
+    fn checked_mul_rejects_overflow() {
+        assert_eq!(Q64::MAX.checked_mul(Q64::from_int(2), Rounding::Floor), Err(MathError::Overflow));
+        assert_eq!(Q64::MAX.checked_mul(Q64::MAX, Rounding::Floor), Err(MathError::Overflow));
+        assert_eq!(Q64::MAX.saturating_mul(Q64::MAX, Rounding::Floor), Q64::MAX);
+    }
+
+    #[test]
+    fn checked_div_vectors() {
+        assert_eq!(Q64::ONE.checked_div(Q64::ONE, Rounding::Floor), Ok(Q64::ONE));
+        assert_eq!(
+            Q64::ONE.checked_div(Q64::from_int(3), Rounding::Floor),
+            Ok(Q64::from_bits(6_148_914_691_236_517_205))
+        );
+        assert_eq!(
+            Q64::ONE.checked_div(Q64::from_int(3), Rounding::Ceil),
+            Ok(Q64::from_bits(6_148_914_691_236_517_206))
+        );
+        assert_eq!(
+            PI_Q64.checked_div(E_Q64, Rounding::Floor),
+            Ok(Q64::from_bits(21_319_406_640_579_731_198))
+        );
+        assert_eq!(
+            E_Q64.checked_div(PI_Q64, Rounding::Floor),
+            Ok(Q64::from_bits(15_961_155_610_833_889_680))
+        );
+        assert_eq!(Q64::MAX.checked_div(Q64::ONE, Rounding::Floor), Ok(Q64::MAX));
+        assert_eq!(Q64::from_bits(1).checked_div(Q64::MAX, Rounding::Floor), Ok(Q64::ZERO));
+    }
+
+    #[test]
+    fn checked_div_rejects_zero_divisor_and_overflow() {
+        assert_eq!(Q64::ONE.checked_div(Q64::ZERO, Rounding::Floor), Err(MathError::DivisionByZero));
+        assert_eq!(
+            Q64::MAX.checked_div(Q64::from_bits(ONE - 1), Rounding::Floor),
+            Err(MathError::Overflow)
+        );
+        assert_eq!(Q64::MAX.checked_div(Q64::from_bits(1), Rounding::Floor), Err(MathError::Overflow));
+        assert_eq!(Q64::ONE.saturating_div(Q64::ZERO, Rounding::Floor), Q64::MAX);
+    }
+
+    #[test]
+    fn add_sub() {
+        assert_eq!(Q64::ONE.checked_add(Q64::ONE), Ok(Q64::from_int(2)));
+        assert_eq!(Q64::MAX.checked_add(Q64::from_bits(1)), Err(MathError::Overflow));
+        assert_eq!(Q64::ZERO.checked_sub(Q64::from_bits(1)), Err(MathError::Overflow));
+        assert_eq!(Q64::MAX.saturating_add(Q64::ONE), Q64::MAX);
+        assert_eq!(Q64::ZERO.saturating_sub(Q64::ONE), Q64::ZERO);
+    }
+
+    #[test]
+    fn from_bps_vectors() {
+        assert_eq!(Q64::from_bps(0, Rounding::Floor), Q64::ZERO);
+        assert_eq!(Q64::from_bps(1, Rounding::Floor), Q64::from_bits(1_844_674_407_370_955));
+        assert_eq!(Q64::from_bps(1, Rounding::Ceil), Q64::from_bits(1_844_674_407_370_956));
+        assert_eq!(Q64::from_bps(5_000, Rounding::Floor), Q64::from_bits(ONE / 2));
+        assert_eq!(Q64::from_bps(8_500, Rounding::Floor), Q64::from_bits(15_679_732_462_653_118_873));
+        assert_eq!(Q64::from_bps(10_000, Rounding::Floor), Q64::ONE);
+        assert_eq!(Q64::from_bps(u16::MAX, Rounding::Floor), Q64::from_bits(120_890_737_287_055_546_515));
+    }
+
+    #[test]
+    fn from_decimal_vectors() {
+        assert_eq!(Q64::from_decimal(100_000_000, -8, Rounding::Floor), Ok(Q64::ONE));
+        assert_eq!(Q64::from_decimal(1, -8, Rounding::Floor), Ok(Q64::from_bits(184_467_440_737)));
+        assert_eq!(Q64::from_decimal(15, 1, Rounding::Floor), Ok(Q64::from_int(150)));
+        assert_eq!(Q64::from_decimal(1, 39, Rounding::Floor), Err(MathError::Overflow));
+        assert_eq!(Q64::from_decimal(1, 20, Rounding::Floor), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn ten_pow_bounds() {
+        assert_eq!(ten_pow(0), Ok(1));
+        assert_eq!(ten_pow(18), Ok(1_000_000_000_000_000_000));
+        assert_eq!(ten_pow(38), Ok(100_000_000_000_000_000_000_000_000_000_000_000_000));
+        assert_eq!(ten_pow(39), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn kamino_fraction_conversions() {
+        let one_sf = 1u128 << 60;
+        assert_eq!(Q64::from_fraction_sf(one_sf), Ok(Q64::ONE));
+        assert_eq!(Q64::from_fraction_sf(3 * one_sf / 2), Ok(Q64::from_bits(3 * ONE / 2)));
+        assert_eq!(Q64::from_fraction_sf(u128::MAX >> 4), Ok(Q64::from_bits(u128::MAX & !0xf)));
+        assert_eq!(Q64::from_fraction_sf((u128::MAX >> 4) + 1), Err(MathError::Overflow));
+
+        assert_eq!(Q64::ONE.to_fraction_sf(Rounding::Floor), one_sf);
+        assert_eq!(Q64::from_bits(ONE + 1).to_fraction_sf(Rounding::Floor), one_sf);
+        assert_eq!(Q64::from_bits(ONE + 1).to_fraction_sf(Rounding::Ceil), one_sf + 1);
+    }
+
+    #[test]
+    fn to_int_rounding() {
+        let two_and_half = Q64::from_bits(5 * ONE / 2);
+        assert_eq!(two_and_half.to_int(Rounding::Floor), 2);
+        assert_eq!(two_and_half.to_int(Rounding::Ceil), 3);
+        assert_eq!(two_and_half.to_int(Rounding::Nearest), 3);
+        assert_eq!(Q64::from_int(7).to_int(Rounding::Ceil), 7);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Q64::ZERO.to_string(), "0");
+        assert_eq!(Q64::from_int(42).to_string(), "42");
+        assert_eq!(Q64::from_bits(3 * ONE / 2).to_string(), "1.5");
+        assert_eq!(Q64::from_bits(1).to_string(), "0.0000000000000000000542101086242752217003726400434970855712890625");
+        assert_eq!(PI_Q64.to_string().get(..22), Some("3.14159265358979323845"));
+        assert_eq!(format!("{:.4}", PI_Q64), "3.1415");
+        assert_eq!(format!("{:.3}", Q64::ONE), "1.000");
+        assert_eq!(format!("{:.0}", PI_Q64), "3");
+        assert_eq!(Q64::MAX.to_string().get(..21), Some("18446744073709551615."));
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("1".parse::<Q64>(), Ok(Q64::ONE));
+        assert_eq!("1.5".parse::<Q64>(), Ok(Q64::from_bits(3 * ONE / 2)));
+        assert_eq!(".5".parse::<Q64>(), Ok(Q64::from_bits(ONE / 2)));
+        assert_eq!("2.".parse::<Q64>(), Ok(Q64::from_int(2)));
+        assert_eq!("3.14159265358979323846".parse::<Q64>(), Ok(PI_Q64));
+        assert_eq!("18446744073709551615".parse::<Q64>(), Ok(Q64::from_int(u64::MAX)));
+
+        assert_eq!("".parse::<Q64>(), Err(ParseQ64Error::Empty));
+        assert_eq!(".".parse::<Q64>(), Err(ParseQ64Error::Empty));
+        assert_eq!("1.2.3".parse::<Q64>(), Err(ParseQ64Error::InvalidDigit));
+        assert_eq!("-1".parse::<Q64>(), Err(ParseQ64Error::InvalidDigit));
+        assert_eq!("18446744073709551616".parse::<Q64>(), Err(ParseQ64Error::Overflow));
+    }
+
+    #[test]
+    fn display_from_str_round_trip() {
+        for bits in [0, 1, ONE - 1, ONE, PI_Q64.to_bits(), E_Q64.to_bits(), u128::MAX] {
+            let q = Q64::from_bits(bits);
+            assert_eq!(q.to_string().parse::<Q64>(), Ok(q));
+        }
+    }
+}
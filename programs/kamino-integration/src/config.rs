@@ -0,0 +1,243 @@
+use anchor_lang::prelude::*;
+
+use crate::oracle::OracleConfig;
+use crate::HfError;
+
+/* Seed of the program's single `Config` PDA. */
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/* Kamino's main lending market, allowed when no market is configured. */
+pub const MAIN_MARKET: Pubkey = pubkey!("7u3HeHxYDLhnCoErrtycNokbQYbWGzLs6JSDqGAv5PfF");
+
+/* Maximum number of lending markets in `Config::allowed_markets`. */
+pub const MAX_ALLOWED_MARKETS: usize = 8;
+
+/* Maximum number of reserves in `Config::allowed_reserves`. */
+pub const MAX_ALLOWED_RESERVES: usize = 64;
+
+/* Admin-controlled settings, as passed to `initialize_config` and `update_config`.
+- The pause flag is not one of them: only `set_paused` changes it, so every change emits `PauseChanged`. */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConfigParams {
+    /// klend deployment whose accounts are accepted and CPI'd into.
+    pub klend_program: Pubkey,
+    /// Lending markets whose obligations may be used; empty means `[MAIN_MARKET]`.
+    pub allowed_markets: Vec<Pubkey>,
+    /// Reserves an obligation may deposit into or borrow from; empty means any reserve of an allowed market.
+    pub allowed_reserves: Vec<Pubkey>,
+    /// Maximum number of slots since a reserve's `refresh_reserve` before its price is rejected.
+    pub max_price_age_slots: u64,
+    /// Upper bound on the caller's `OracleConfig::max_age_secs`.
+    pub max_oracle_age_secs: u64,
+    /// Upper bound on the caller's `OracleConfig::max_conf_bps`, at most 10_000.
+    pub max_oracle_conf_bps: u16,
+    /// Default HF (Q64.64) below which monitors should warn the owner.
+    pub warning_hf_q64: u128,
+    /// Default HF (Q64.64) below which monitors should treat the position as critical.
+    pub critical_hf_q64: u128,
+}
+
+impl ConfigParams {
+    pub fn validate(&self) -> Result<()> {
+        require_keys_neq!(self.klend_program, Pubkey::default(), HfError::InvalidConfig);
+        require!(self.allowed_markets.len() <= MAX_ALLOWED_MARKETS, HfError::InvalidConfig);
+        require!(self.allowed_reserves.len() <= MAX_ALLOWED_RESERVES, HfError::InvalidConfig);
+        require!(!has_duplicates(&self.allowed_markets), HfError::InvalidConfig);
+        require!(!has_duplicates(&self.allowed_reserves), HfError::InvalidConfig);
+        require!(
+            self.max_price_age_slots > 0 && self.max_oracle_age_secs > 0,
+            HfError::InvalidConfig
+        );
+        require!(
+            self.max_oracle_conf_bps > 0 && self.max_oracle_conf_bps <= 10_000,
+            HfError::InvalidConfig
+        );
+        require!(self.critical_hf_q64 <= self.warning_hf_q64, HfError::InvalidConfig);
+        Ok(())
+    }
+}
+
+/* Program-wide settings, seeded `["config"]` and initialized once by the program's upgrade authority.
+- `admin` changes settings with `update_config` and hands over control with `transfer_admin` + `accept_admin`. */
+#[account]
+#[derive(InitSpace)]
+pub struct Config {
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>,
+    pub klend_program: Pubkey,
+    #[max_len(MAX_ALLOWED_MARKETS)]
+    pub allowed_markets: Vec<Pubkey>,
+    #[max_len(MAX_ALLOWED_RESERVES)]
+    pub allowed_reserves: Vec<Pubkey>,
+    pub max_price_age_slots: u64,
+    pub max_oracle_age_secs: u64,
+    pub max_oracle_conf_bps: u16,
+    pub warning_hf_q64: u128,
+    pub critical_hf_q64: u128,
+    pub paused: bool,
+}
+
+impl Config {
+    pub const SPACE: usize = 8 + Config::INIT_SPACE;
+
+    /* Replaces every admin-controlled setting except `paused`; `params` must already be validated. */
+    pub fn apply(&mut self, params: ConfigParams) {
+        self.klend_program = params.klend_program;
+        self.allowed_markets = if params.allowed_markets.is_empty() {
+            vec![MAIN_MARKET]
+        } else {
+            params.allowed_markets
+        };
+        self.allowed_reserves = params.allowed_reserves;
+        self.max_price_age_slots = params.max_price_age_slots;
+        self.max_oracle_age_secs = params.max_oracle_age_secs;
+        self.max_oracle_conf_bps = params.max_oracle_conf_bps;
+        self.warning_hf_q64 = params.warning_hf_q64;
+        self.critical_hf_q64 = params.critical_hf_q64;
+    }
+
+    pub fn require_not_paused(&self) -> Result<()> {
+        require!(!self.paused, HfError::Paused);
+        Ok(())
+    }
+
+    pub fn require_market(&self, market: &Pubkey) -> Result<()> {
+        if !self.allowed_markets.contains(market) {
+            msg!("Lending market {} is not in the config allowlist", market);
+            return err!(HfError::MarketNotAllowed);
+        }
+        Ok(())
+    }
+
+    /* Checks a reserve against `allowed_reserves`; every reserve passes when the list is empty. */
+    pub fn require_reserve(&self, reserve: &Pubkey) -> Result<()> {
+        if !self.allowed_reserves.is_empty() && !self.allowed_reserves.contains(reserve) {
+            msg!("Reserve {} is not in the config allowlist", reserve);
+            return err!(HfError::ReserveNotAllowed);
+        }
+        Ok(())
+    }
+
+    /* The caller's oracle limits, capped at `max_oracle_age_secs` and `max_oracle_conf_bps`. */
+    pub fn oracle_config(&self, config: OracleConfig) -> OracleConfig {
+        OracleConfig {
+            max_age_secs: config.max_age_secs.min(self.max_oracle_age_secs),
+            max_conf_bps: config.max_conf_bps.min(self.max_oracle_conf_bps),
+        }
+    }
+}
+
+fn has_duplicates(keys: &[Pubkey]) -> bool {
+    keys.iter().enumerate().any(|(i, key)| keys[..i].contains(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::klend::{KLEND_PROGRAM_ID, MAX_PRICE_AGE_SLOTS};
+
+    fn params() -> ConfigParams {
+        ConfigParams {
+            klend_program: KLEND_PROGRAM_ID,
+            allowed_markets: vec![Pubkey::new_unique()],
+            allowed_reserves: vec![],
+            max_price_age_slots: MAX_PRICE_AGE_SLOTS,
+            max_oracle_age_secs: 60,
+            max_oracle_conf_bps: 200,
+            warning_hf_q64: 3 << 63,
+            critical_hf_q64: 1 << 64,
+        }
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_params() {
+        assert!(params().validate().is_ok());
+
+        let invalid: Error = HfError::InvalidConfig.into();
+        let cases: [fn(&mut ConfigParams); 9] = [
+            |p| p.klend_program = Pubkey::default(),
+            |p| p.allowed_markets.push(p.allowed_markets[0]),
+            |p| p.allowed_markets = (0..=MAX_ALLOWED_MARKETS).map(|_| Pubkey::new_unique()).collect(),
+            |p| p.allowed_reserves = vec![MAIN_MARKET; 2],
+            |p| p.allowed_reserves = (0..=MAX_ALLOWED_RESERVES).map(|_| Pubkey::new_unique()).collect(),
+            |p| p.max_price_age_slots = 0,
+            |p| p.max_oracle_conf_bps = 0,
+            |p| p.max_oracle_conf_bps = 10_001,
+            |p| p.critical_hf_q64 = p.warning_hf_q64 + 1,
+        ];
+        for mutate in cases {
+            let mut p = params();
+            mutate(&mut p);
+            assert_eq!(p.validate().unwrap_err(), invalid);
+        }
+    }
+
+    #[test]
+    fn markets_default_to_main_market_and_reserves_to_any() {
+        let mut p = params();
+        p.allowed_markets.clear();
+        assert!(p.validate().is_ok());
+
+        let mut config = Config::try_deserialize_unchecked(&mut &[0u8; Config::SPACE][..]).unwrap();
+        config.apply(p);
+        assert_eq!(config.allowed_markets, vec![MAIN_MARKET]);
+        assert!(config.require_market(&MAIN_MARKET).is_ok());
+        assert!(config.require_reserve(&Pubkey::new_unique()).is_ok());
+
+        let reserve = Pubkey::new_unique();
+        config.allowed_reserves = vec![reserve];
+        assert!(config.require_reserve(&reserve).is_ok());
+        let not_allowed: Error = HfError::ReserveNotAllowed.into();
+        assert_eq!(config.require_reserve(&Pubkey::new_unique()).unwrap_err(), not_allowed);
+    }
+
+    #[test]
+    fn checks_markets_pause_and_oracle_limits() {
+        let p = params();
+        let mut config = Config {
+            admin: Pubkey::new_unique(),
+            pending_admin: None,
+            klend_program: Pubkey::default(),
+            allowed_markets: vec![],
+            allowed_reserves: vec![],
+            max_price_age_slots: 0,
+            max_oracle_age_secs: 0,
+            max_oracle_conf_bps: 0,
+            warning_hf_q64: 0,
+            critical_hf_q64: 0,
+            paused: true,
+        };
+        config.apply(p.clone());
+
+        assert!(config.require_market(&p.allowed_markets[0]).is_ok());
+        let not_allowed: Error = HfError::MarketNotAllowed.into();
+        assert_eq!(config.require_market(&Pubkey::new_unique()).unwrap_err(), not_allowed);
+
+        // applying new settings leaves the pause flag to `set_paused`
+        let paused: Error = HfError::Paused.into();
+        assert_eq!(config.require_not_paused().unwrap_err(), paused);
+        config.paused = false;
+        assert!(config.require_not_paused().is_ok());
+
+        let capped = config.oracle_config(OracleConfig { max_age_secs: 600, max_conf_bps: 10_000 });
+        assert_eq!((capped.max_age_secs, capped.max_conf_bps), (60, 200));
+        let within = config.oracle_config(OracleConfig { max_age_secs: 30, max_conf_bps: 100 });
+        assert_eq!((within.max_age_secs, within.max_conf_bps), (30, 100));
+
+        let mut full = params();
+        full.allowed_markets = (0..MAX_ALLOWED_MARKETS).map(|_| Pubkey::new_unique()).collect();
+        full.allowed_reserves = (0..MAX_ALLOWED_RESERVES).map(|_| Pubkey::new_unique()).collect();
+        config.apply(full);
+        config.pending_admin = Some(Pubkey::new_unique());
+        let mut data = Vec::new();
+        config.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), Config::SPACE);
+
+        config.apply(p.clone());
+        let mut data = Vec::new();
+        config.try_serialize(&mut data).unwrap();
+        assert!(data.len() <= Config::SPACE);
+        let decoded = Config::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!((decoded.allowed_markets, decoded.paused), (p.allowed_markets, false));
+    }
+}
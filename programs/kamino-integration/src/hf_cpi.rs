@@ -0,0 +1,18 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::get_return_data;
+
+use crate::{HfBreakdown, HfError};
+
+/* Calls `get_hf` and decodes the `HfBreakdown` it returns.
+- Pass the obligation's reserves with `CpiContext::with_remaining_accounts`. */
+pub fn get_hf<'info>(ctx: CpiContext<'_, '_, '_, 'info, crate::cpi::accounts::GetHf<'info>>) -> Result<HfBreakdown> {
+    crate::cpi::get_hf(ctx)?;
+    read_hf_return_data()
+}
+
+/* Decodes the `HfBreakdown` left in return data by the last call into this program. */
+pub fn read_hf_return_data() -> Result<HfBreakdown> {
+    let (program_id, data) = get_return_data().ok_or(HfError::InvalidReturnData)?;
+    require_keys_eq!(program_id, crate::ID, HfError::InvalidReturnData);
+    HfBreakdown::from_return_data(&data)
+}
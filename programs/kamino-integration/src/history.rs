@@ -0,0 +1,124 @@
+use anchor_lang::prelude::*;
+
+/* Number of samples kept per obligation; older samples are overwritten. */
+pub const HF_HISTORY_LEN: usize = 64;
+
+/* Most samples `get_hf_history` can return within the 1024-byte return data limit. */
+pub const MAX_HISTORY_WINDOW: usize = 15;
+
+/* One HF computation, values in Q64.64 (collateral is the unweighted USD value).
+- Plain borsh derives: anchor's would clash with the IDL impl generated by `zero_copy`. */
+#[zero_copy]
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize, Debug, PartialEq, Eq)]
+pub struct HfSample {
+    pub slot: u64,
+    pub timestamp: i64,
+    pub hf_q64: u128,
+    pub total_collateral_q64: u128,
+    pub total_debt_q64: u128,
+}
+
+/* Ring buffer of one obligation's last `HF_HISTORY_LEN` HF samples, seeded `["hf_history", market, obligation]`
+and written by every HF-storing instruction.
+- `user` is the obligation's owner, who alone may close the account.
+- `head` is the slot the next sample is written to; `count` saturates at `HF_HISTORY_LEN`. */
+#[account(zero_copy)]
+pub struct HfHistory {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub obligation: Pubkey,
+    pub head: u32,
+    pub count: u32,
+    // keeps `samples` 16-byte aligned on every target
+    pub _padding: [u8; 8],
+    pub samples: [HfSample; HF_HISTORY_LEN],
+}
+
+impl HfHistory {
+    /* Appends a sample, overwriting the oldest one once the buffer is full. */
+    pub fn push(&mut self, sample: HfSample) {
+        self.samples[self.head as usize] = sample;
+        self.head = (self.head + 1) % HF_HISTORY_LEN as u32;
+        self.count = (self.count + 1).min(HF_HISTORY_LEN as u32);
+    }
+
+    /* Samples taken at or after `from_slot`, oldest first, at most `max_samples` of the newest ones. */
+    pub fn window(&self, from_slot: u64, max_samples: usize) -> Vec<HfSample> {
+        let count = self.count as usize;
+        let oldest = (self.head as usize + HF_HISTORY_LEN - count) % HF_HISTORY_LEN;
+        let matching: Vec<HfSample> = (0..count)
+            .map(|i| self.samples[(oldest + i) % HF_HISTORY_LEN])
+            .filter(|s| s.slot >= from_slot)
+            .collect();
+
+        matching[matching.len().saturating_sub(max_samples)..].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(slot: u64) -> HfSample {
+        HfSample {
+            slot,
+            timestamp: slot as i64 * 2,
+            hf_q64: slot as u128,
+            total_collateral_q64: 0,
+            total_debt_q64: 0,
+        }
+    }
+
+    fn empty() -> HfHistory {
+        HfHistory {
+            user: Pubkey::default(),
+            market: Pubkey::default(),
+            obligation: Pubkey::default(),
+            head: 0,
+            count: 0,
+            _padding: [0; 8],
+            samples: [sample(0); HF_HISTORY_LEN],
+        }
+    }
+
+    fn slots(samples: &[HfSample]) -> Vec<u64> {
+        samples.iter().map(|s| s.slot).collect()
+    }
+
+    #[test]
+    fn layout_has_no_implicit_padding() {
+        assert_eq!(std::mem::size_of::<HfSample>(), 64);
+        assert_eq!(std::mem::size_of::<HfHistory>(), 112 + 64 * HF_HISTORY_LEN);
+    }
+
+    #[test]
+    fn window_before_wrap() {
+        let mut history = empty();
+        assert!(history.window(0, MAX_HISTORY_WINDOW).is_empty());
+        for slot in 1..=3 {
+            history.push(sample(slot));
+        }
+        assert_eq!(slots(&history.window(0, MAX_HISTORY_WINDOW)), vec![1, 2, 3]);
+        assert_eq!(slots(&history.window(2, MAX_HISTORY_WINDOW)), vec![2, 3]);
+        assert_eq!(slots(&history.window(0, 1)), vec![3]);
+    }
+
+    #[test]
+    fn oldest_samples_are_overwritten() {
+        let mut history = empty();
+        for slot in 1..=(HF_HISTORY_LEN as u64 + 10) {
+            history.push(sample(slot));
+        }
+        assert_eq!(history.count as usize, HF_HISTORY_LEN);
+        assert_eq!(history.head, 10);
+
+        let all = history.window(0, HF_HISTORY_LEN);
+        assert_eq!(all.len(), HF_HISTORY_LEN);
+        assert_eq!(all.first().unwrap().slot, 11);
+        assert_eq!(all.last().unwrap().slot, HF_HISTORY_LEN as u64 + 10);
+
+        let recent = history.window(0, MAX_HISTORY_WINDOW);
+        assert_eq!(slots(&recent), (60..=74).collect::<Vec<_>>());
+        assert!(recent.try_to_vec().unwrap().len() <= anchor_lang::solana_program::program::MAX_RETURN_DATA);
+    }
+}
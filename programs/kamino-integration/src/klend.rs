@@ -0,0 +1,913 @@
+use std::cell::Ref;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use ethereum_types::U256;
+use hf_math::{Q64, Rounding, KAMINO_FRACTION_BITS};
+
+use crate::oracle::OraclePrice;
+use crate::{CollateralPosition, DebtPosition, HfError, HfInputs};
+
+/* Mainnet Kamino Lending (klend) program id; the accepted deployment is `Config::klend_program`. */
+pub const KLEND_PROGRAM_ID: Pubkey = pubkey!("KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD");
+
+/* Anchor account discriminators of the klend accounts we decode. */
+pub const OBLIGATION_DISCRIMINATOR: [u8; 8] = [168, 206, 141, 106, 88, 76, 172, 167];
+pub const RESERVE_DISCRIMINATOR: [u8; 8] = [43, 242, 204, 202, 26, 247, 59, 127];
+pub const LENDING_MARKET_DISCRIMINATOR: [u8; 8] = [246, 114, 50, 98, 72, 157, 28, 120];
+
+/* Anchor instruction discriminators of the klend instructions we CPI into. */
+const LIQUIDATE_OBLIGATION_AND_REDEEM_RESERVE_COLLATERAL_DISCRIMINATOR: [u8; 8] = [177, 71, 154, 188, 226, 133, 74, 55];
+
+/* klend stores fixed-point values ("_sf") scaled by 2^60. */
+const FRACTION_BITS: u32 = KAMINO_FRACTION_BITS;
+
+/* Suggested `Config::max_price_age_slots`: slots since the reserve's last `refresh_reserve` before its price is rejected. */
+pub const MAX_PRICE_AGE_SLOTS: u64 = 25;
+
+/* klend's slot-based year, used to turn a borrow APR into a per-slot rate. */
+pub const SLOTS_PER_YEAR: u64 = 63_072_000;
+
+/* klend's `PriceStatusFlags::ALL_CHECKS`: every price check `refresh_reserve` can pass. */
+pub const PRICE_STATUS_ALL_CHECKS: u8 = 0b0011_1111;
+
+// --------------- Obligation layout ---------------
+// Offsets are relative to the start of the account data (discriminator included).
+
+const OBLIGATION_SIZE: usize = 8 + 3336;
+const OBLIGATION_LENDING_MARKET: usize = 8 + 24;
+const OBLIGATION_OWNER: usize = 8 + 56;
+const OBLIGATION_DEPOSITS: usize = 8 + 88;
+const OBLIGATION_DEPOSITS_LEN: usize = 8;
+const OBLIGATION_COLLATERAL_SIZE: usize = 136;
+const OBLIGATION_BORROWS: usize = 8 + 1200;
+const OBLIGATION_BORROWS_LEN: usize = 5;
+const OBLIGATION_LIQUIDITY_SIZE: usize = 200;
+const OBLIGATION_ELEVATION_GROUP: usize = 8 + 2277;
+
+// ObligationCollateral { deposit_reserve, deposited_amount, .. }
+const COLLATERAL_RESERVE: usize = 0;
+const COLLATERAL_DEPOSITED_AMOUNT: usize = 32;
+
+// ObligationLiquidity { borrow_reserve, cumulative_borrow_rate_bsf, padding, borrowed_amount_sf, .. }
+const LIQUIDITY_RESERVE: usize = 0;
+const LIQUIDITY_CUMULATIVE_BORROW_RATE_BSF: usize = 32;
+const LIQUIDITY_BORROWED_AMOUNT_SF: usize = 88;
+
+// --------------- Reserve layout ---------------
+
+const RESERVE_SIZE: usize = 8 + 8616;
+const RESERVE_LAST_UPDATE_SLOT: usize = 8 + 8;
+const RESERVE_LAST_UPDATE_STALE: usize = 8 + 16;
+const RESERVE_LAST_UPDATE_PRICE_STATUS: usize = 8 + 17;
+const RESERVE_LENDING_MARKET: usize = 8 + 24;
+const RESERVE_LIQUIDITY_AVAILABLE_AMOUNT: usize = 8 + 216;
+const RESERVE_LIQUIDITY_BORROWED_AMOUNT_SF: usize = 8 + 224;
+const RESERVE_LIQUIDITY_MARKET_PRICE_SF: usize = 8 + 240;
+const RESERVE_LIQUIDITY_MINT_DECIMALS: usize = 8 + 264;
+const RESERVE_LIQUIDITY_CUMULATIVE_BORROW_RATE_BSF: usize = 8 + 288;
+const RESERVE_LIQUIDITY_PROTOCOL_FEES_SF: usize = 8 + 336;
+const RESERVE_LIQUIDITY_REFERRER_FEES_SF: usize = 8 + 352;
+const RESERVE_LIQUIDITY_PENDING_REFERRER_FEES_SF: usize = 8 + 368;
+const RESERVE_COLLATERAL_MINT_TOTAL_SUPPLY: usize = 8 + 2584;
+const RESERVE_CONFIG_HOST_FIXED_INTEREST_RATE_BPS: usize = 8 + 4850;
+const RESERVE_CONFIG_LOAN_TO_VALUE_PCT: usize = 8 + 4864;
+const RESERVE_CONFIG_LIQUIDATION_THRESHOLD_PCT: usize = 8 + 4865;
+const RESERVE_CONFIG_MIN_LIQUIDATION_BONUS_BPS: usize = 8 + 4866;
+const RESERVE_CONFIG_MAX_LIQUIDATION_BONUS_BPS: usize = 8 + 4868;
+const RESERVE_CONFIG_BAD_DEBT_LIQUIDATION_BONUS_BPS: usize = 8 + 4870;
+const RESERVE_CONFIG_BORROW_RATE_CURVE: usize = 8 + 4912;
+const BORROW_RATE_CURVE_POINTS: usize = 11;
+const RESERVE_CONFIG_BORROW_FACTOR_PCT: usize = 8 + 5000;
+const RESERVE_CONFIG_SCOPE_PRICE_FEED: usize = 8 + 5104;
+const RESERVE_CONFIG_SCOPE_PRICE_CHAIN: usize = 8 + 5136;
+const RESERVE_CONFIG_SWITCHBOARD_PRICE_AGGREGATOR: usize = 8 + 5152;
+const RESERVE_CONFIG_PYTH_PRICE: usize = 8 + 5216;
+
+// --------------- LendingMarket layout ---------------
+
+const LENDING_MARKET_SIZE: usize = 8 + 4656;
+const LENDING_MARKET_LIQUIDATION_MAX_DEBT_CLOSE_FACTOR_PCT: usize = 8 + 118;
+const LENDING_MARKET_INSOLVENCY_RISK_UNHEALTHY_LTV_PCT: usize = 8 + 119;
+const LENDING_MARKET_MIN_FULL_LIQUIDATION_VALUE_THRESHOLD: usize = 8 + 120;
+const LENDING_MARKET_MAX_LIQUIDATABLE_DEBT_MARKET_VALUE_AT_ONCE: usize = 8 + 128;
+const LENDING_MARKET_ELEVATION_GROUPS: usize = 8 + 192;
+const LENDING_MARKET_ELEVATION_GROUPS_LEN: usize = 32;
+const ELEVATION_GROUP_SIZE: usize = 72;
+
+// ElevationGroup { max_liquidation_bonus_bps, id, ltv_pct, liquidation_threshold_pct, allow_new_loans,
+//                  max_reserves_as_collateral, padding, debt_reserve, .. }
+const ELEVATION_GROUP_MAX_LIQUIDATION_BONUS_BPS: usize = 0;
+const ELEVATION_GROUP_ID: usize = 2;
+const ELEVATION_GROUP_LTV_PCT: usize = 3;
+const ELEVATION_GROUP_LIQUIDATION_THRESHOLD_PCT: usize = 4;
+const ELEVATION_GROUP_ALLOW_NEW_LOANS: usize = 5;
+const ELEVATION_GROUP_MAX_RESERVES_AS_COLLATERAL: usize = 6;
+const ELEVATION_GROUP_DEBT_RESERVE: usize = 8;
+
+/* A single non-empty deposit slot of a klend obligation. */
+#[derive(Clone, Debug)]
+pub struct ObligationDeposit {
+    pub reserve: Pubkey,
+    pub deposited_amount: u64,
+}
+
+/* A single non-empty borrow slot of a klend obligation. */
+#[derive(Clone, Debug)]
+pub struct ObligationBorrow {
+    pub reserve: Pubkey,
+    /// The reserve's cumulative borrow rate when `borrowed_amount_sf` was last accrued.
+    pub cumulative_borrow_rate_bsf: U256,
+    pub borrowed_amount_sf: u128,
+}
+
+impl ObligationBorrow {
+    /* The borrowed amount accrued to `current_slot` as a 2^60 fixed-point number, rounded up.
+    - Scales `borrowed_amount_sf` by the reserve's cumulative borrow rate (projected to `current_slot`)
+      over the rate stored at the obligation's last refresh, like klend's `accrue_interest`. */
+    pub fn borrowed_amount_at(&self, reserve: &Reserve, current_slot: u64) -> Result<u128> {
+        let cumulative_borrow_rate_bsf = reserve.cumulative_borrow_rate_at(current_slot)?;
+        require!(
+            !self.cumulative_borrow_rate_bsf.is_zero() && cumulative_borrow_rate_bsf >= self.cumulative_borrow_rate_bsf,
+            HfError::InvalidCumulativeBorrowRate
+        );
+
+        let accrued = U256::from(self.borrowed_amount_sf)
+            .checked_mul(cumulative_borrow_rate_bsf)
+            .map(|v| div_ceil(v, self.cumulative_borrow_rate_bsf))
+            .ok_or(HfError::MathOverflow)?;
+        require!(accrued <= U256::from(u128::MAX), HfError::MathOverflow);
+
+        Ok(accrued.as_u128())
+    }
+}
+
+/* The subset of a klend `Obligation` account needed to compute HF. */
+#[derive(Clone, Debug)]
+pub struct Obligation {
+    pub lending_market: Pubkey,
+    pub owner: Pubkey,
+    /// Elevation group (eMode) id; 0 when the obligation is not in a group.
+    pub elevation_group: u8,
+    pub deposits: Vec<ObligationDeposit>,
+    pub borrows: Vec<ObligationBorrow>,
+}
+
+impl Obligation {
+    /* Decodes an obligation after checking its owner program and discriminator. */
+    pub fn load(info: &AccountInfo, klend_program: &Pubkey) -> Result<Self> {
+        let data = load_klend_account(info, klend_program, &OBLIGATION_DISCRIMINATOR, OBLIGATION_SIZE)?;
+
+        let mut deposits = Vec::new();
+        for i in 0..OBLIGATION_DEPOSITS_LEN {
+            let base = OBLIGATION_DEPOSITS + i * OBLIGATION_COLLATERAL_SIZE;
+            let reserve = read_pubkey(&data, base + COLLATERAL_RESERVE);
+            if reserve == Pubkey::default() {
+                continue;
+            }
+            deposits.push(ObligationDeposit {
+                reserve,
+                deposited_amount: read_u64(&data, base + COLLATERAL_DEPOSITED_AMOUNT),
+            });
+        }
+
+        let mut borrows = Vec::new();
+        for i in 0..OBLIGATION_BORROWS_LEN {
+            let base = OBLIGATION_BORROWS + i * OBLIGATION_LIQUIDITY_SIZE;
+            let reserve = read_pubkey(&data, base + LIQUIDITY_RESERVE);
+            if reserve == Pubkey::default() {
+                continue;
+            }
+            borrows.push(ObligationBorrow {
+                reserve,
+                cumulative_borrow_rate_bsf: read_big_fraction(&data, base + LIQUIDITY_CUMULATIVE_BORROW_RATE_BSF),
+                borrowed_amount_sf: read_u128(&data, base + LIQUIDITY_BORROWED_AMOUNT_SF),
+            });
+        }
+
+        Ok(Self {
+            lending_market: read_pubkey(&data, OBLIGATION_LENDING_MARKET),
+            owner: read_pubkey(&data, OBLIGATION_OWNER),
+            elevation_group: data[OBLIGATION_ELEVATION_GROUP],
+            deposits,
+            borrows,
+        })
+    }
+}
+
+/* The subset of a klend `Reserve` account needed to compute HF. */
+#[derive(Clone, Debug, Default)]
+pub struct Reserve {
+    pub lending_market: Pubkey,
+    /// `last_update.slot`: when `refresh_reserve` last updated the price and accrued interest.
+    pub price_last_updated_slot: u64,
+    /// `last_update.stale`: set by klend when the reserve changed after its last refresh.
+    pub stale: bool,
+    /// `last_update.price_status`: the `PriceStatusFlags` the last `refresh_reserve` passed.
+    pub price_status: u8,
+    pub mint_decimals: u64,
+    pub available_amount: u64,
+    pub borrowed_amount_sf: u128,
+    pub market_price_sf: u128,
+    pub accumulated_protocol_fees_sf: u128,
+    pub accumulated_referrer_fees_sf: u128,
+    pub pending_referrer_fees_sf: u128,
+    pub cumulative_borrow_rate_bsf: U256,
+    pub collateral_mint_total_supply: u64,
+    pub host_fixed_interest_rate_bps: u16,
+    pub loan_to_value_pct: u8,
+    pub liquidation_threshold_pct: u8,
+    pub min_liquidation_bonus_bps: u16,
+    pub max_liquidation_bonus_bps: u16,
+    pub bad_debt_liquidation_bonus_bps: u16,
+    /// (utilization_rate_bps, borrow_rate_bps) points, increasing up to 100% utilization.
+    pub borrow_rate_curve: [(u32, u32); BORROW_RATE_CURVE_POINTS],
+    pub borrow_factor_pct: u64,
+    pub scope_price_feed: Pubkey,
+    pub scope_price_chain: [u16; 4],
+    pub switchboard_price_aggregator: Pubkey,
+    pub pyth_price: Pubkey,
+}
+
+impl Reserve {
+    /* Decodes a reserve after checking its owner program and discriminator. */
+    pub fn load(info: &AccountInfo, klend_program: &Pubkey) -> Result<Self> {
+        let data = load_klend_account(info, klend_program, &RESERVE_DISCRIMINATOR, RESERVE_SIZE)?;
+
+        Ok(Self {
+            lending_market: read_pubkey(&data, RESERVE_LENDING_MARKET),
+            price_last_updated_slot: read_u64(&data, RESERVE_LAST_UPDATE_SLOT),
+            stale: data[RESERVE_LAST_UPDATE_STALE] != 0,
+            price_status: data[RESERVE_LAST_UPDATE_PRICE_STATUS],
+            mint_decimals: read_u64(&data, RESERVE_LIQUIDITY_MINT_DECIMALS),
+            available_amount: read_u64(&data, RESERVE_LIQUIDITY_AVAILABLE_AMOUNT),
+            borrowed_amount_sf: read_u128(&data, RESERVE_LIQUIDITY_BORROWED_AMOUNT_SF),
+            market_price_sf: read_u128(&data, RESERVE_LIQUIDITY_MARKET_PRICE_SF),
+            accumulated_protocol_fees_sf: read_u128(&data, RESERVE_LIQUIDITY_PROTOCOL_FEES_SF),
+            accumulated_referrer_fees_sf: read_u128(&data, RESERVE_LIQUIDITY_REFERRER_FEES_SF),
+            pending_referrer_fees_sf: read_u128(&data, RESERVE_LIQUIDITY_PENDING_REFERRER_FEES_SF),
+            cumulative_borrow_rate_bsf: read_big_fraction(&data, RESERVE_LIQUIDITY_CUMULATIVE_BORROW_RATE_BSF),
+            collateral_mint_total_supply: read_u64(&data, RESERVE_COLLATERAL_MINT_TOTAL_SUPPLY),
+            host_fixed_interest_rate_bps: read_u16(&data, RESERVE_CONFIG_HOST_FIXED_INTEREST_RATE_BPS),
+            loan_to_value_pct: data[RESERVE_CONFIG_LOAN_TO_VALUE_PCT],
+            liquidation_threshold_pct: data[RESERVE_CONFIG_LIQUIDATION_THRESHOLD_PCT],
+            min_liquidation_bonus_bps: read_u16(&data, RESERVE_CONFIG_MIN_LIQUIDATION_BONUS_BPS),
+            max_liquidation_bonus_bps: read_u16(&data, RESERVE_CONFIG_MAX_LIQUIDATION_BONUS_BPS),
+            bad_debt_liquidation_bonus_bps: read_u16(&data, RESERVE_CONFIG_BAD_DEBT_LIQUIDATION_BONUS_BPS),
+            borrow_rate_curve: core::array::from_fn(|i| {
+                let base = RESERVE_CONFIG_BORROW_RATE_CURVE + 8 * i;
+                (read_u32(&data, base), read_u32(&data, base + 4))
+            }),
+            borrow_factor_pct: read_u64(&data, RESERVE_CONFIG_BORROW_FACTOR_PCT),
+            scope_price_feed: read_pubkey(&data, RESERVE_CONFIG_SCOPE_PRICE_FEED),
+            scope_price_chain: core::array::from_fn(|i| read_u16(&data, RESERVE_CONFIG_SCOPE_PRICE_CHAIN + 2 * i)),
+            switchboard_price_aggregator: read_pubkey(&data, RESERVE_CONFIG_SWITCHBOARD_PRICE_AGGREGATOR),
+            pyth_price: read_pubkey(&data, RESERVE_CONFIG_PYTH_PRICE),
+        })
+    }
+
+    /* Total liquidity (available + borrowed - fees) as a 2^60 fixed-point number. */
+    fn total_supply_sf(&self) -> Result<u128> {
+        let available_sf = (self.available_amount as u128) << FRACTION_BITS;
+        available_sf
+            .checked_add(self.borrowed_amount_sf)
+            .and_then(|v| v.checked_sub(self.accumulated_protocol_fees_sf))
+            .and_then(|v| v.checked_sub(self.accumulated_referrer_fees_sf))
+            .and_then(|v| v.checked_sub(self.pending_referrer_fees_sf))
+            .ok_or(HfError::MathOverflow.into())
+    }
+
+    /* Converts deposited cTokens into underlying liquidity, rounding down like klend. */
+    pub fn collateral_to_liquidity(&self, collateral_amount: u64) -> Result<u64> {
+        let total_supply_sf = self.total_supply_sf()?;
+        if self.collateral_mint_total_supply == 0 || total_supply_sf == 0 {
+            return Ok(collateral_amount);
+        }
+
+        let liquidity = (U256::from(collateral_amount) * U256::from(total_supply_sf)
+            / U256::from(self.collateral_mint_total_supply))
+            >> FRACTION_BITS;
+        require!(liquidity <= U256::from(u64::MAX), HfError::MathOverflow);
+
+        Ok(liquidity.as_u64())
+    }
+
+    /* The borrow APR at the reserve's current utilization, as a 2^60 fixed-point number.
+    - Interpolates linearly between the surrounding curve points, like klend's `BorrowRateCurve::get_borrow_rate`;
+      utilization and the interpolated rate are rounded up.
+    - Excludes `host_fixed_interest_rate_bps`, which `cumulative_borrow_rate_at` adds on top. */
+    pub fn borrow_rate_sf(&self) -> Result<U256> {
+        let bps = U256::from(10_000u32);
+        let total_supply_sf = self.total_supply_sf()?;
+        // utilization in basis points, 2^60 fixed point
+        let utilization = if total_supply_sf == 0 {
+            U256::zero()
+        } else {
+            div_ceil((U256::from(self.borrowed_amount_sf) * bps) << FRACTION_BITS, U256::from(total_supply_sf))
+                .min(bps << FRACTION_BITS)
+        };
+
+        let sf = |v: u32| U256::from(v) << FRACTION_BITS;
+        let segment = self
+            .borrow_rate_curve
+            .windows(2)
+            .find(|s| utilization <= sf(s[1].0))
+            .ok_or(HfError::InvalidBorrowRateCurve)?;
+        let ((start_utilization, start_rate), (end_utilization, end_rate)) = (segment[0], segment[1]);
+        require!(
+            start_utilization <= end_utilization && start_rate <= end_rate && sf(start_utilization) <= utilization,
+            HfError::InvalidBorrowRateCurve
+        );
+
+        let rate_bps = if end_utilization == start_utilization {
+            sf(start_rate)
+        } else {
+            sf(start_rate)
+                + div_ceil(
+                    (utilization - sf(start_utilization)) * U256::from(end_rate - start_rate),
+                    U256::from(end_utilization - start_utilization),
+                )
+        };
+
+        Ok(div_ceil(rate_bps, bps))
+    }
+
+    /* The reserve's cumulative borrow rate as of `current_slot`.
+    - When the reserve was not refreshed this slot, compounds it per slot at the current borrow APR
+      plus the host fixed rate over the elapsed slots, as klend's `refresh_reserve` would, rounding up. */
+    pub fn cumulative_borrow_rate_at(&self, current_slot: u64) -> Result<U256> {
+        let elapsed_slots = current_slot.saturating_sub(self.price_last_updated_slot);
+        if elapsed_slots == 0 {
+            return Ok(self.cumulative_borrow_rate_bsf);
+        }
+
+        let host_rate = div_ceil(U256::from(self.host_fixed_interest_rate_bps) << FRACTION_BITS, U256::from(10_000u32));
+        let slot_rate = div_ceil(self.borrow_rate_sf()? + host_rate, U256::from(SLOTS_PER_YEAR));
+        let compounded = pow_sf_ceil((U256::one() << FRACTION_BITS) + slot_rate, elapsed_slots)?;
+
+        mul_sf_ceil(self.cumulative_borrow_rate_bsf, compounded)
+    }
+
+    /* Returns the reserve's market price in Q64.64, rejecting prices older than `max_age_slots`.
+    - klend refreshes `market_price_sf` and `last_update.slot` together in `refresh_reserve`.
+    - Like klend's `refresh_obligation`, also rejects reserves marked `stale` or whose `price_status`
+      misses any of `PRICE_STATUS_ALL_CHECKS`. */
+    pub fn price_q64(&self, current_slot: u64, max_age_slots: u64) -> Result<u128> {
+        require!(self.market_price_sf > 0, HfError::InvalidPrice);
+        require!(
+            current_slot.saturating_sub(self.price_last_updated_slot) <= max_age_slots,
+            HfError::StalePrice
+        );
+        require!(!self.stale, HfError::StalePrice);
+        require!(
+            self.price_status & PRICE_STATUS_ALL_CHECKS == PRICE_STATUS_ALL_CHECKS,
+            HfError::StalePrice
+        );
+
+        Ok(Q64::from_fraction_sf(self.market_price_sf).map_err(HfError::from)?.to_bits())
+    }
+}
+
+/* An elevation group (eMode) of a klend market.
+- Obligations in the group value every collateral at the group's LTV and liquidation threshold
+  instead of the reserves' own ones. */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ElevationGroup {
+    pub id: u8,
+    pub max_liquidation_bonus_bps: u16,
+    pub ltv_pct: u8,
+    pub liquidation_threshold_pct: u8,
+    pub allow_new_loans: bool,
+    pub max_reserves_as_collateral: u8,
+    pub debt_reserve: Pubkey,
+}
+
+/* The liquidation and elevation group settings of a klend `LendingMarket` account. */
+#[derive(Clone, Debug)]
+pub struct LendingMarket {
+    pub liquidation_max_debt_close_factor_pct: u8,
+    pub insolvency_risk_unhealthy_ltv_pct: u8,
+    /// Obligations with less debt than this (USD) may be liquidated in full.
+    pub min_full_liquidation_value_threshold: u64,
+    /// Maximum debt value (USD) repaid by a single liquidation.
+    pub max_liquidatable_debt_market_value_at_once: u64,
+    pub elevation_groups: Vec<ElevationGroup>,
+}
+
+impl LendingMarket {
+    /* Decodes a lending market after checking its owner program and discriminator. */
+    pub fn load(info: &AccountInfo, klend_program: &Pubkey) -> Result<Self> {
+        let data = load_klend_account(info, klend_program, &LENDING_MARKET_DISCRIMINATOR, LENDING_MARKET_SIZE)?;
+
+        Ok(Self {
+            liquidation_max_debt_close_factor_pct: data[LENDING_MARKET_LIQUIDATION_MAX_DEBT_CLOSE_FACTOR_PCT],
+            insolvency_risk_unhealthy_ltv_pct: data[LENDING_MARKET_INSOLVENCY_RISK_UNHEALTHY_LTV_PCT],
+            min_full_liquidation_value_threshold: read_u64(&data, LENDING_MARKET_MIN_FULL_LIQUIDATION_VALUE_THRESHOLD),
+            max_liquidatable_debt_market_value_at_once: read_u64(
+                &data,
+                LENDING_MARKET_MAX_LIQUIDATABLE_DEBT_MARKET_VALUE_AT_ONCE,
+            ),
+            elevation_groups: (0..LENDING_MARKET_ELEVATION_GROUPS_LEN)
+                .map(|i| {
+                    let base = LENDING_MARKET_ELEVATION_GROUPS + i * ELEVATION_GROUP_SIZE;
+                    ElevationGroup {
+                        id: data[base + ELEVATION_GROUP_ID],
+                        max_liquidation_bonus_bps: read_u16(&data, base + ELEVATION_GROUP_MAX_LIQUIDATION_BONUS_BPS),
+                        ltv_pct: data[base + ELEVATION_GROUP_LTV_PCT],
+                        liquidation_threshold_pct: data[base + ELEVATION_GROUP_LIQUIDATION_THRESHOLD_PCT],
+                        allow_new_loans: data[base + ELEVATION_GROUP_ALLOW_NEW_LOANS] != 0,
+                        max_reserves_as_collateral: data[base + ELEVATION_GROUP_MAX_RESERVES_AS_COLLATERAL],
+                        debt_reserve: read_pubkey(&data, base + ELEVATION_GROUP_DEBT_RESERVE),
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /* Returns elevation group `id` (stored at index `id - 1`, as in klend), or `None` for group 0.
+    - Fails if the slot is not configured for that id or its thresholds are invalid. */
+    pub fn elevation_group(&self, id: u8) -> Result<Option<ElevationGroup>> {
+        if id == 0 {
+            return Ok(None);
+        }
+        let group = self
+            .elevation_groups
+            .get(id as usize - 1)
+            .filter(|g| g.id == id)
+            .ok_or(HfError::InvalidElevationGroup)?;
+        require!(
+            group.liquidation_threshold_pct > 0
+                && group.liquidation_threshold_pct <= 100
+                && group.ltv_pct <= group.liquidation_threshold_pct,
+            HfError::InvalidElevationGroup
+        );
+
+        Ok(Some(group.clone()))
+    }
+}
+
+/* Builds `HfInputs` from an obligation and its reserves.
+- `reserves` must list the deposit reserves followed by the borrow reserves,
+  in the order they appear in the obligation (same as klend's `refresh_obligation`),
+  and be owned by `klend_program`.
+- `elevation_group` is the obligation's group (see `LendingMarket::elevation_group`); when set,
+  its LTV and liquidation threshold replace every collateral reserve's and borrow factors are ignored, as in klend.
+- Borrowed amounts include the interest accrued up to `current_slot` (see `ObligationBorrow::borrowed_amount_at`).
+- `price` prices the asset at the given index (deposits first, then borrows), rounding with the given mode:
+  `Floor` for collateral and `Ceil` for debt, so rounding never overstates HF. */
+pub fn hf_inputs_from_obligation(
+    obligation: &Obligation,
+    reserves: &[AccountInfo],
+    klend_program: &Pubkey,
+    elevation_group: Option<&ElevationGroup>,
+    current_slot: u64,
+    mut price: impl FnMut(usize, &Reserve, Rounding) -> Result<OraclePrice>,
+) -> Result<HfInputs> {
+    require!(
+        reserves.len() == obligation.deposits.len() + obligation.borrows.len(),
+        HfError::ReserveAccountsMismatch
+    );
+    let (deposit_reserves, borrow_reserves) = reserves.split_at(obligation.deposits.len());
+
+    let mut collaterals = Vec::with_capacity(obligation.deposits.len());
+    for (i, (deposit, info)) in obligation.deposits.iter().zip(deposit_reserves).enumerate() {
+        let reserve = load_obligation_reserve(obligation, &deposit.reserve, info, klend_program)?;
+        let price = price(i, &reserve, Rounding::Floor)?;
+
+        collaterals.push(CollateralPosition {
+            amount: reserve.collateral_to_liquidity(deposit.deposited_amount)?,
+            decimals: mint_decimals(&reserve)?,
+            price_q64: price.price_q64,
+            conservative_price_q64: price.lower_bound_q64(),
+            max_ltv_bps: elevation_group.map_or(reserve.loan_to_value_pct, |g| g.ltv_pct) as u16 * 100,
+            liq_threshold_bps: elevation_group
+                .map_or(reserve.liquidation_threshold_pct, |g| g.liquidation_threshold_pct) as u16
+                * 100,
+            borrow_factor_bps: 0,
+        });
+    }
+
+    let mut debts = Vec::with_capacity(obligation.borrows.len());
+    for (i, (borrow, info)) in obligation.borrows.iter().zip(borrow_reserves).enumerate() {
+        let reserve = load_obligation_reserve(obligation, &borrow.reserve, info, klend_program)?;
+        let price = price(obligation.deposits.len() + i, &reserve, Rounding::Ceil)?;
+
+        debts.push(DebtPosition {
+            amount: sf_to_u64_ceil(borrow.borrowed_amount_at(&reserve, current_slot)?)?,
+            decimals: mint_decimals(&reserve)?,
+            price_q64: price.price_q64,
+            conservative_price_q64: price.upper_bound_q64()?,
+            borrow_factor_bps: match elevation_group {
+                Some(_) => 0,
+                None => borrow_factor_bps(&reserve)?,
+            },
+        });
+    }
+
+    Ok(HfInputs { collaterals, debts })
+}
+
+/* Loads a reserve and checks it is the one referenced by the obligation slot. */
+fn load_obligation_reserve(
+    obligation: &Obligation,
+    expected: &Pubkey,
+    info: &AccountInfo,
+    klend_program: &Pubkey,
+) -> Result<Reserve> {
+    require_keys_eq!(info.key(), *expected, HfError::ReserveAccountsMismatch);
+    let reserve = Reserve::load(info, klend_program)?;
+    require_keys_eq!(reserve.lending_market, obligation.lending_market, HfError::LendingMarketMismatch);
+
+    Ok(reserve)
+}
+
+/* klend's `borrow_factor_pct` in basis points. */
+fn borrow_factor_bps(reserve: &Reserve) -> Result<u32> {
+    reserve
+        .borrow_factor_pct
+        .checked_mul(100)
+        .and_then(|bps| u32::try_from(bps).ok())
+        .ok_or(HfError::InvalidBorrowFactor.into())
+}
+
+#[inline(always)]
+fn mint_decimals(reserve: &Reserve) -> Result<u8> {
+    u8::try_from(reserve.mint_decimals).map_err(|_| HfError::InvalidDecimals.into())
+}
+
+/* Rounds a 2^60 fixed-point amount up to whole token units. */
+fn sf_to_u64_ceil(value_sf: u128) -> Result<u64> {
+    let mask = (1u128 << FRACTION_BITS) - 1;
+    let whole = (value_sf >> FRACTION_BITS) + u128::from(value_sf & mask != 0);
+
+    u64::try_from(whole).map_err(|_| HfError::MathOverflow.into())
+}
+
+#[inline(always)]
+fn div_ceil(numerator: U256, denominator: U256) -> U256 {
+    let (quotient, remainder) = numerator.div_mod(denominator);
+    quotient + U256::from(u8::from(!remainder.is_zero()))
+}
+
+/* Multiplies two 2^60 fixed-point numbers, rounding up. */
+fn mul_sf_ceil(a: U256, b: U256) -> Result<U256> {
+    let product = a.checked_mul(b).ok_or(HfError::MathOverflow)?;
+    Ok(div_ceil(product, U256::one() << FRACTION_BITS))
+}
+
+/* Raises a 2^60 fixed-point number to an integer power by squaring, rounding every step up. */
+fn pow_sf_ceil(mut base: U256, mut exp: u64) -> Result<U256> {
+    let mut result = U256::one() << FRACTION_BITS;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_sf_ceil(result, base)?;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = mul_sf_ceil(base, base)?;
+        }
+    }
+    Ok(result)
+}
+
+// --------------- CPI ---------------
+
+/* Accounts of klend's `liquidate_obligation_and_redeem_reserve_collateral`, in instruction order. */
+pub struct LiquidateObligationAccounts<'info> {
+    pub liquidator: AccountInfo<'info>,
+    pub obligation: AccountInfo<'info>,
+    pub lending_market: AccountInfo<'info>,
+    pub lending_market_authority: AccountInfo<'info>,
+    pub repay_reserve: AccountInfo<'info>,
+    pub repay_reserve_liquidity_mint: AccountInfo<'info>,
+    pub repay_reserve_liquidity_supply: AccountInfo<'info>,
+    pub withdraw_reserve: AccountInfo<'info>,
+    pub withdraw_reserve_liquidity_mint: AccountInfo<'info>,
+    pub withdraw_reserve_collateral_mint: AccountInfo<'info>,
+    pub withdraw_reserve_collateral_supply: AccountInfo<'info>,
+    pub withdraw_reserve_liquidity_supply: AccountInfo<'info>,
+    pub withdraw_reserve_liquidity_fee_receiver: AccountInfo<'info>,
+    pub user_source_liquidity: AccountInfo<'info>,
+    pub user_destination_collateral: AccountInfo<'info>,
+    pub user_destination_liquidity: AccountInfo<'info>,
+    pub collateral_token_program: AccountInfo<'info>,
+    pub repay_liquidity_token_program: AccountInfo<'info>,
+    pub withdraw_liquidity_token_program: AccountInfo<'info>,
+    pub instruction_sysvar_account: AccountInfo<'info>,
+}
+
+impl<'info> LiquidateObligationAccounts<'info> {
+    fn to_account_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new_readonly(self.liquidator.key(), true),
+            AccountMeta::new(self.obligation.key(), false),
+            AccountMeta::new_readonly(self.lending_market.key(), false),
+            AccountMeta::new_readonly(self.lending_market_authority.key(), false),
+            AccountMeta::new(self.repay_reserve.key(), false),
+            AccountMeta::new_readonly(self.repay_reserve_liquidity_mint.key(), false),
+            AccountMeta::new(self.repay_reserve_liquidity_supply.key(), false),
+            AccountMeta::new(self.withdraw_reserve.key(), false),
+            AccountMeta::new_readonly(self.withdraw_reserve_liquidity_mint.key(), false),
+            AccountMeta::new(self.withdraw_reserve_collateral_mint.key(), false),
+            AccountMeta::new(self.withdraw_reserve_collateral_supply.key(), false),
+            AccountMeta::new(self.withdraw_reserve_liquidity_supply.key(), false),
+            AccountMeta::new(self.withdraw_reserve_liquidity_fee_receiver.key(), false),
+            AccountMeta::new(self.user_source_liquidity.key(), false),
+            AccountMeta::new(self.user_destination_collateral.key(), false),
+            AccountMeta::new(self.user_destination_liquidity.key(), false),
+            AccountMeta::new_readonly(self.collateral_token_program.key(), false),
+            AccountMeta::new_readonly(self.repay_liquidity_token_program.key(), false),
+            AccountMeta::new_readonly(self.withdraw_liquidity_token_program.key(), false),
+            AccountMeta::new_readonly(self.instruction_sysvar_account.key(), false),
+        ]
+    }
+
+    fn to_account_infos(&self) -> Vec<AccountInfo<'info>> {
+        vec![
+            self.liquidator.clone(),
+            self.obligation.clone(),
+            self.lending_market.clone(),
+            self.lending_market_authority.clone(),
+            self.repay_reserve.clone(),
+            self.repay_reserve_liquidity_mint.clone(),
+            self.repay_reserve_liquidity_supply.clone(),
+            self.withdraw_reserve.clone(),
+            self.withdraw_reserve_liquidity_mint.clone(),
+            self.withdraw_reserve_collateral_mint.clone(),
+            self.withdraw_reserve_collateral_supply.clone(),
+            self.withdraw_reserve_liquidity_supply.clone(),
+            self.withdraw_reserve_liquidity_fee_receiver.clone(),
+            self.user_source_liquidity.clone(),
+            self.user_destination_collateral.clone(),
+            self.user_destination_liquidity.clone(),
+            self.collateral_token_program.clone(),
+            self.repay_liquidity_token_program.clone(),
+            self.withdraw_liquidity_token_program.clone(),
+            self.instruction_sysvar_account.clone(),
+        ]
+    }
+}
+
+/* CPI into klend's `liquidate_obligation_and_redeem_reserve_collateral`.
+- Repays up to `liquidity_amount` of debt and redeems the seized collateral to liquidity;
+  klend rejects the liquidation if less than `min_acceptable_received_liquidity_amount` is received.
+- klend requires its `refresh_reserve`/`refresh_obligation` instructions earlier in the same transaction.
+- `klend_program` is invoked as-is; callers must have checked it against `Config::klend_program`. */
+pub fn liquidate_obligation_and_redeem_reserve_collateral<'info>(
+    klend_program: &AccountInfo<'info>,
+    accounts: &LiquidateObligationAccounts<'info>,
+    liquidity_amount: u64,
+    min_acceptable_received_liquidity_amount: u64,
+    max_allowed_ltv_override_percent: u64,
+) -> Result<()> {
+    let mut data = Vec::with_capacity(8 + 3 * 8);
+    data.extend_from_slice(&LIQUIDATE_OBLIGATION_AND_REDEEM_RESERVE_COLLATERAL_DISCRIMINATOR);
+    data.extend_from_slice(&liquidity_amount.to_le_bytes());
+    data.extend_from_slice(&min_acceptable_received_liquidity_amount.to_le_bytes());
+    data.extend_from_slice(&max_allowed_ltv_override_percent.to_le_bytes());
+
+    let ix = Instruction {
+        program_id: klend_program.key(),
+        accounts: accounts.to_account_metas(),
+        data,
+    };
+    let mut infos = accounts.to_account_infos();
+    infos.push(klend_program.clone());
+    invoke(&ix, &infos)?;
+
+    Ok(())
+}
+
+// --------------- Raw account access ---------------
+
+fn load_klend_account<'a, 'info>(
+    info: &'a AccountInfo<'info>,
+    klend_program: &Pubkey,
+    discriminator: &[u8; 8],
+    min_len: usize,
+) -> Result<Ref<'a, [u8]>> {
+    require_keys_eq!(*info.owner, *klend_program, HfError::InvalidAccountOwner);
+    let data = info.try_borrow_data()?;
+    require!(
+        data.len() >= min_len && data[..8] == discriminator[..],
+        HfError::InvalidAccountDiscriminator
+    );
+
+    Ok(Ref::map(data, |d| &d[..]))
+}
+
+#[inline(always)]
+fn read_pubkey(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::new_from_array(data[offset..offset + 32].try_into().unwrap())
+}
+
+#[inline(always)]
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+#[inline(always)]
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[inline(always)]
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[inline(always)]
+fn read_u128(data: &[u8], offset: usize) -> u128 {
+    u128::from_le_bytes(data[offset..offset + 16].try_into().unwrap())
+}
+
+/* Reads a klend `BigFractionBytes` (a 2^60 fixed-point `[u64; 4]` followed by padding). */
+#[inline(always)]
+fn read_big_fraction(data: &[u8], offset: usize) -> U256 {
+    U256::from_little_endian(&data[offset..offset + 32])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account_data(discriminator: &[u8; 8], size: usize) -> Vec<u8> {
+        let mut data = vec![0u8; size];
+        data[..8].copy_from_slice(discriminator);
+        data
+    }
+
+    fn group(id: u8, ltv_pct: u8, liquidation_threshold_pct: u8) -> ElevationGroup {
+        ElevationGroup {
+            id,
+            max_liquidation_bonus_bps: 200,
+            ltv_pct,
+            liquidation_threshold_pct,
+            allow_new_loans: true,
+            max_reserves_as_collateral: 2,
+            debt_reserve: Pubkey::new_unique(),
+        }
+    }
+
+    fn write_group(data: &mut [u8], index: usize, g: &ElevationGroup) {
+        let base = LENDING_MARKET_ELEVATION_GROUPS + index * ELEVATION_GROUP_SIZE;
+        data[base + ELEVATION_GROUP_MAX_LIQUIDATION_BONUS_BPS..][..2].copy_from_slice(&g.max_liquidation_bonus_bps.to_le_bytes());
+        data[base + ELEVATION_GROUP_ID] = g.id;
+        data[base + ELEVATION_GROUP_LTV_PCT] = g.ltv_pct;
+        data[base + ELEVATION_GROUP_LIQUIDATION_THRESHOLD_PCT] = g.liquidation_threshold_pct;
+        data[base + ELEVATION_GROUP_ALLOW_NEW_LOANS] = g.allow_new_loans as u8;
+        data[base + ELEVATION_GROUP_MAX_RESERVES_AS_COLLATERAL] = g.max_reserves_as_collateral;
+        data[base + ELEVATION_GROUP_DEBT_RESERVE..][..32].copy_from_slice(g.debt_reserve.as_ref());
+    }
+
+    /* A reserve with `available` tokens and `borrowed` tokens lent out, on a 0% -> 10% (80%) -> 100% rate curve. */
+    fn reserve(available: u64, borrowed: u64) -> Reserve {
+        let mut data = account_data(&RESERVE_DISCRIMINATOR, RESERVE_SIZE);
+        data[RESERVE_LAST_UPDATE_SLOT..][..8].copy_from_slice(&100u64.to_le_bytes());
+        data[RESERVE_LIQUIDITY_AVAILABLE_AMOUNT..][..8].copy_from_slice(&available.to_le_bytes());
+        data[RESERVE_LIQUIDITY_BORROWED_AMOUNT_SF..][..16].copy_from_slice(&((borrowed as u128) << FRACTION_BITS).to_le_bytes());
+        // cumulative borrow rate 1.1
+        data[RESERVE_LIQUIDITY_CUMULATIVE_BORROW_RATE_BSF..][..16].copy_from_slice(&((11u128 << FRACTION_BITS) / 10).to_le_bytes());
+        let curve = [(0u32, 0u32), (8_000, 1_000)].into_iter().chain(std::iter::repeat((10_000, 10_000)));
+        for (i, (utilization, rate)) in curve.take(BORROW_RATE_CURVE_POINTS).enumerate() {
+            let base = RESERVE_CONFIG_BORROW_RATE_CURVE + 8 * i;
+            data[base..][..4].copy_from_slice(&utilization.to_le_bytes());
+            data[base + 4..][..4].copy_from_slice(&rate.to_le_bytes());
+        }
+
+        let (key, mut lamports) = (Pubkey::new_unique(), 0);
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &KLEND_PROGRAM_ID, false, 0);
+        Reserve::load(&info, &KLEND_PROGRAM_ID).unwrap()
+    }
+
+    #[test]
+    fn borrow_rate_is_interpolated_on_the_curve() {
+        let bps_sf = |bps: u64| div_ceil(U256::from(bps) << FRACTION_BITS, U256::from(10_000u64));
+
+        assert_eq!(reserve(1_000, 0).borrow_rate_sf().unwrap(), U256::zero());
+        assert_eq!(reserve(600, 400).borrow_rate_sf().unwrap(), bps_sf(500));
+        assert_eq!(reserve(200, 800).borrow_rate_sf().unwrap(), bps_sf(1_000));
+        assert_eq!(reserve(100, 900).borrow_rate_sf().unwrap(), bps_sf(5_500));
+        assert_eq!(reserve(0, 1_000).borrow_rate_sf().unwrap(), bps_sf(10_000));
+
+        let mut broken = reserve(100, 900);
+        broken.borrow_rate_curve[2] = (10_000, 500);
+        let invalid: Error = HfError::InvalidBorrowRateCurve.into();
+        assert_eq!(broken.borrow_rate_sf().unwrap_err(), invalid);
+    }
+
+    #[test]
+    fn borrows_accrue_interest_up_to_the_current_slot() {
+        let reserve = reserve(600, 400);
+        let borrow = ObligationBorrow {
+            reserve: Pubkey::new_unique(),
+            cumulative_borrow_rate_bsf: U256::one() << FRACTION_BITS,
+            borrowed_amount_sf: 1_000u128 << FRACTION_BITS,
+        };
+
+        // refreshed this slot: scaled by the stored cumulative rate only
+        assert_eq!(borrow.borrowed_amount_at(&reserve, 100).unwrap(), 1_000 * ((11u128 << FRACTION_BITS) / 10));
+
+        // 1/1000 of a year at 5% APR, compounded per slot
+        let slots = SLOTS_PER_YEAR / 1_000;
+        let accrued = borrow.borrowed_amount_at(&reserve, 100 + slots).unwrap();
+        let expected = 1_100.0 * (1.0 + 0.05 / SLOTS_PER_YEAR as f64).powi(slots as i32);
+        let actual = accrued as f64 / (1u128 << FRACTION_BITS) as f64;
+        assert!(actual >= expected * (1.0 - 1e-12) && actual <= expected * (1.0 + 1e-9), "{actual} vs {expected}");
+
+        // the host fixed rate is compounded on top of the curve rate
+        let hosted = Reserve { host_fixed_interest_rate_bps: 300, ..reserve.clone() };
+        let accrued = borrow.borrowed_amount_at(&hosted, 100 + slots).unwrap();
+        let expected = 1_100.0 * (1.0 + 0.08 / SLOTS_PER_YEAR as f64).powi(slots as i32);
+        let actual = accrued as f64 / (1u128 << FRACTION_BITS) as f64;
+        assert!(actual >= expected * (1.0 - 1e-12) && actual <= expected * (1.0 + 1e-9), "{actual} vs {expected}");
+
+        let ahead = ObligationBorrow { cumulative_borrow_rate_bsf: U256::from(2u8) << FRACTION_BITS, ..borrow };
+        let invalid: Error = HfError::InvalidCumulativeBorrowRate.into();
+        assert_eq!(ahead.borrowed_amount_at(&reserve, 100).unwrap_err(), invalid);
+    }
+
+    #[test]
+    fn reserve_price_requires_a_recent_fully_checked_refresh() {
+        let load = |stale: u8, price_status: u8| {
+            let mut data = account_data(&RESERVE_DISCRIMINATOR, RESERVE_SIZE);
+            data[RESERVE_LAST_UPDATE_SLOT..][..8].copy_from_slice(&100u64.to_le_bytes());
+            data[RESERVE_LAST_UPDATE_STALE] = stale;
+            data[RESERVE_LAST_UPDATE_PRICE_STATUS] = price_status;
+            data[RESERVE_LIQUIDITY_MARKET_PRICE_SF..][..16].copy_from_slice(&(150u128 << FRACTION_BITS).to_le_bytes());
+            let (key, mut lamports) = (Pubkey::new_unique(), 0);
+            let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &KLEND_PROGRAM_ID, false, 0);
+            Reserve::load(&info, &KLEND_PROGRAM_ID).unwrap()
+        };
+        let stale_price: Error = HfError::StalePrice.into();
+
+        let fresh = load(0, PRICE_STATUS_ALL_CHECKS);
+        assert_eq!(fresh.price_q64(110, 10).unwrap(), 150 << 64);
+        assert_eq!(fresh.price_q64(111, 10).unwrap_err(), stale_price);
+
+        // marked stale by klend, or refreshed without every price check passing
+        assert_eq!(load(1, PRICE_STATUS_ALL_CHECKS).price_q64(100, 10).unwrap_err(), stale_price);
+        assert_eq!(load(0, PRICE_STATUS_ALL_CHECKS & !0b10_0000).price_q64(100, 10).unwrap_err(), stale_price);
+        assert_eq!(load(0, 0).price_q64(100, 10).unwrap_err(), stale_price);
+    }
+
+    #[test]
+    fn elevation_group_is_looked_up_by_id() {
+        let lst = group(2, 85, 90);
+        let mut data = account_data(&LENDING_MARKET_DISCRIMINATOR, LENDING_MARKET_SIZE);
+        write_group(&mut data, 1, &lst);
+        write_group(&mut data, 2, &group(3, 95, 90));
+
+        let (key, mut lamports) = (Pubkey::new_unique(), 0);
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &KLEND_PROGRAM_ID, false, 0);
+        let market = LendingMarket::load(&info, &KLEND_PROGRAM_ID).unwrap();
+
+        assert_eq!(market.elevation_group(0).unwrap(), None);
+        assert_eq!(market.elevation_group(2).unwrap(), Some(lst));
+        let invalid: Error = HfError::InvalidElevationGroup.into();
+        // unconfigured slot, LTV above the liquidation threshold, out of range
+        for id in [1, 3, 33] {
+            assert_eq!(market.elevation_group(id).unwrap_err(), invalid);
+        }
+    }
+
+    #[test]
+    fn elevation_group_thresholds_replace_reserve_thresholds() {
+        let market = Pubkey::new_unique();
+        let reserve_key = Pubkey::new_unique();
+        let mut data = account_data(&RESERVE_DISCRIMINATOR, RESERVE_SIZE);
+        data[RESERVE_LENDING_MARKET..][..32].copy_from_slice(market.as_ref());
+        data[RESERVE_LIQUIDITY_MINT_DECIMALS..][..8].copy_from_slice(&9u64.to_le_bytes());
+        data[RESERVE_LIQUIDITY_MARKET_PRICE_SF..][..16].copy_from_slice(&(150u128 << FRACTION_BITS).to_le_bytes());
+        data[RESERVE_LAST_UPDATE_PRICE_STATUS] = PRICE_STATUS_ALL_CHECKS;
+        data[RESERVE_CONFIG_LOAN_TO_VALUE_PCT] = 65;
+        data[RESERVE_CONFIG_LIQUIDATION_THRESHOLD_PCT] = 75;
+
+        let mut lamports = 0;
+        let info = AccountInfo::new(&reserve_key, false, false, &mut lamports, &mut data, &KLEND_PROGRAM_ID, false, 0);
+        let obligation = Obligation {
+            lending_market: market,
+            owner: Pubkey::new_unique(),
+            elevation_group: 2,
+            deposits: vec![ObligationDeposit { reserve: reserve_key, deposited_amount: 1_000_000_000 }],
+            borrows: vec![],
+        };
+        let threshold = |g: Option<&ElevationGroup>| {
+            let inputs = hf_inputs_from_obligation(&obligation, std::slice::from_ref(&info), &KLEND_PROGRAM_ID, g, 0, |_, r, _| {
+                r.price_q64(0, MAX_PRICE_AGE_SLOTS).map(OraclePrice::spot)
+            })
+            .unwrap();
+            (inputs.collaterals[0].max_ltv_bps, inputs.collaterals[0].liq_threshold_bps)
+        };
+
+        assert_eq!(threshold(None), (6_500, 7_500));
+        assert_eq!(threshold(Some(&group(2, 85, 90))), (8_500, 9_000));
+
+        let other_program = Pubkey::new_unique();
+        let err = hf_inputs_from_obligation(&obligation, std::slice::from_ref(&info), &other_program, None, 0, |_, _, _| {
+            unreachable!()
+        });
+        assert_eq!(err.unwrap_err(), HfError::InvalidAccountOwner.into());
+    }
+}
@@ -1,60 +1,998 @@
 use anchor_lang::prelude::*;
-use ethereum_types::U256;
+use anchor_lang::solana_program::program::{set_return_data, MAX_RETURN_DATA};
+use hf_math::{ten_pow, MathError, Q64, Rounding};
+
+pub mod config;
+#[cfg(feature = "cpi")]
+pub mod hf_cpi;
+pub mod history;
+pub mod klend;
+pub mod liquidation;
+pub mod oracle;
+
+use config::{Config, ConfigParams, CONFIG_SEED};
+use history::{HfHistory, HfSample, MAX_HISTORY_WINDOW};
+use liquidation::LiquidationParams;
+use oracle::{OracleConfig, OraclePrice, PriceSourceKind};
 
 declare_id!("8jNJWhcS2kyT6iLhWdogWpiZ7RehkqzPuUiCaSpv9zFA");
 
-const ONE_Q64_64: u128 = 1u128 << 64; // 1.0 in Q64.64
+const PRICE_E8_SCALE: u128 = 100_000_000; // 1.0 in price_e8
 
-#[program]
-pub mod kamino_integration {
+// `#[program]` emits its handlers beside the module it annotates, and anchor's generated IDL handlers
+// still call the deprecated `AccountInfo::realloc`; this wrapper scopes the allow to that code.
+// Everything it defines is re-exported at the crate root.
+#[allow(deprecated)]
+mod program_module {
     use super::*;
 
-    /* Computes a user’s Health Factor (HF) = total collateral / total debt.
-    - Collaterals are weighted by liquidation thresholds and borrow factors.
-    - HF < 1.0 indicates risk of liquidation. */
-    pub fn compute_hf(ctx: Context<ComputeHf>, args: ComputeArgs) -> Result<()> {
-        let hf_q64 = compute_hf_internal(&args)?;
-
-        let state: &mut Account<'_, HfState> = &mut ctx.accounts.hf_state;
-        state.last_hf_q64 = hf_q64;
-        state.user = ctx.accounts.user.key();
-        state.last_update_slot = Clock::get()?.slot;
-
-        emit!(HealthFactorComputed {
-            user: ctx.accounts.user.key(),
-            hf_q64,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+    #[program]
+    pub mod kamino_integration {
+        use super::*;
+
+        /* Computes a user’s Health Factor (HF) = total collateral / total debt from caller-supplied inputs.
+        - Collaterals are weighted by liquidation thresholds and debts by borrow factors.
+        - HF < 1.0 indicates risk of liquidation.
+        - The HF is stored for the given obligation, which must belong to the user. The inputs are not
+          verified, so the `HfState` is marked `HfPricing::CallerInputs` and nothing is appended to
+          `HfHistory`; use `compute_hf_from_obligation` for an HF others can rely on.
+        - Enforces the `Config` like the other HF-storing instructions: fails while paused, and for obligations
+          of another klend program or outside the allowed markets and reserves. */
+        pub fn compute_hf(ctx: Context<ComputeHf>, args: ComputeArgs) -> Result<()> {
+            let a = &ctx.accounts;
+            a.config.require_not_paused()?;
+            load_user_obligation(&a.config, &a.obligation, &a.user.key(), &a.lending_market.key())?;
+
+            let breakdown = compute_hf_internal(&args.to_inputs()?, PriceMode::Spot)?;
+            let key = HfStateKey::new(&a.user, &a.lending_market, &a.obligation);
+            store_hf(&mut ctx.accounts.hf_state, None, key, breakdown, HfPricing::CallerInputs)
+        }
+
+        /* Computes a user’s HF from their Kamino obligation instead of caller-supplied inputs.
+        - Remaining accounts: the obligation's deposit reserves, then its borrow reserves, in slot order.
+        - Amounts, decimals, prices and liquidation thresholds are all read from klend accounts.
+        - Reserves whose price was not refreshed within `config.max_price_age_slots`, or that klend
+          considers stale, are rejected (see `klend::Reserve::price_q64`). */
+        pub fn compute_hf_from_obligation(ctx: Context<ComputeHfFromObligation>) -> Result<()> {
+            let a = &ctx.accounts;
+            a.config.require_not_paused()?;
+            let obligation = load_user_obligation(&a.config, &a.obligation, &a.user.key(), &a.lending_market.key())?;
+
+            let inputs = obligation_inputs(&ctx.accounts.config, &obligation, &ctx.accounts.lending_market, ctx.remaining_accounts)?;
+            let breakdown = compute_hf_internal(&inputs, PriceMode::Spot)?;
+            let key = HfStateKey::new(&a.user, &a.lending_market, &a.obligation);
+            store_hf(&mut ctx.accounts.hf_state, Some(&ctx.accounts.hf_history), key, breakdown, HfPricing::ReservePrices)
+        }
+
+        /* Computes a user’s HF from their Kamino obligation, pricing every asset directly from an oracle.
+        - `sources` selects Scope, Switchboard On-Demand or Pyth per asset (deposits first, then borrows).
+        - Remaining accounts: the obligation's reserves (as in `compute_hf_from_obligation`),
+          followed by one price account per reserve, in the same order.
+        - Each price account must be the feed configured on its reserve, no older than
+          `config.max_age_secs` (capped at `Config::max_oracle_age_secs`) and with a
+          confidence/price ratio within `config.max_conf_bps` (capped at `Config::max_oracle_conf_bps`).
+        - The stored `HfState` is marked `HfPricing::ConservativeOraclePrices` with `conservative`,
+          `HfPricing::OraclePrices` otherwise.
+        - With `conservative`, a second HF is computed with collateral at min(spot, EMA, spot − conf)
+          and debt at max(spot, EMA, spot + conf), returned as `HfBreakdown::conservative_hf_q64`. */
+        pub fn compute_hf_from_oracles(
+            ctx: Context<ComputeHfFromObligation>,
+            config: OracleConfig,
+            sources: Vec<PriceSourceKind>,
+            conservative: bool,
+        ) -> Result<()> {
+            config.validate()?;
+            let a = &ctx.accounts;
+            a.config.require_not_paused()?;
+            let config = a.config.oracle_config(config);
+            let obligation = load_user_obligation(&a.config, &a.obligation, &a.user.key(), &a.lending_market.key())?;
+
+            let asset_count = obligation.deposits.len() + obligation.borrows.len();
+            require!(sources.len() == asset_count, HfError::PriceSourcesMismatch);
+            require!(
+                ctx.remaining_accounts.len() == 2 * asset_count,
+                HfError::ReserveAccountsMismatch
+            );
+            let (reserves, oracles) = ctx.remaining_accounts.split_at(asset_count);
+
+            let clock = Clock::get()?;
+            let group = load_elevation_group(&a.config, &obligation, &a.lending_market)?;
+            let inputs = klend::hf_inputs_from_obligation(
+                &obligation,
+                reserves,
+                &a.config.klend_program,
+                group.as_ref(),
+                clock.slot,
+                |i, reserve, rounding| sources[i].source().price(reserve, &oracles[i], &config, clock.unix_timestamp, rounding),
+            )?;
+            let mut breakdown = compute_hf_internal(&inputs, PriceMode::Spot)?;
+            let pricing = if conservative {
+                breakdown.conservative_hf_q64 = Some(compute_hf_internal(&inputs, PriceMode::Conservative)?.hf_q64);
+                HfPricing::ConservativeOraclePrices
+            } else {
+                HfPricing::OraclePrices
+            };
+            let key = HfStateKey::new(&a.user, &a.lending_market, &a.obligation);
+            store_hf(&mut ctx.accounts.hf_state, Some(&ctx.accounts.hf_history), key, breakdown, pricing)
+        }
+
+        /* Recomputes and stores any obligation's HF on behalf of its owner; callable by any signer (e.g. keepers).
+        - Prices come from the refreshed klend reserves, as in `compute_hf_from_obligation`.
+        - The keeper pays rent only when the obligation's `HfState` or `HfHistory` is first created. */
+        pub fn refresh_hf(ctx: Context<RefreshHf>) -> Result<()> {
+            let a = &ctx.accounts;
+            a.config.require_not_paused()?;
+            let owner = a.owner.key();
+            let obligation = load_user_obligation(&a.config, &a.obligation, &owner, &a.lending_market.key())?;
+
+            let inputs = obligation_inputs(&ctx.accounts.config, &obligation, &ctx.accounts.lending_market, ctx.remaining_accounts)?;
+            let breakdown = compute_hf_internal(&inputs, PriceMode::Spot)?;
+            let key = HfStateKey::new(&a.owner, &a.lending_market, &a.obligation);
+            store_hf(&mut ctx.accounts.hf_state, Some(&ctx.accounts.hf_history), key, breakdown, HfPricing::ReservePrices)
+        }
+
+        /* Computes any obligation's HF without storing it, for CPI callers and simulations.
+        - Read-only: no signer, no `HfState` and no rent.
+        - Remaining accounts: the obligation's reserves, as in `compute_hf_from_obligation`.
+        - The Borsh-encoded `HfBreakdown` is written with `set_return_data`; CPI callers can use `hf_cpi::get_hf`. */
+        pub fn get_hf(ctx: Context<GetHf>) -> Result<()> {
+            let obligation = load_obligation(&ctx.accounts.config, &ctx.accounts.obligation)?;
+            let inputs = obligation_inputs(&ctx.accounts.config, &obligation, &ctx.accounts.lending_market, ctx.remaining_accounts)?;
+            return_breakdown(&compute_hf_internal(&inputs, PriceMode::Spot)?)
+        }
+
+        /* Fails with `HfError::Unhealthy` if an obligation's HF is below `min_hf_q64` (Q64.64).
+        - Append it after Kamino borrows/withdrawals (or CPI into it via `cpi::assert_healthy`)
+          so the whole transaction reverts if the position ends up unsafe.
+        - Accounts and remaining accounts as in `get_hf`; on success the `HfBreakdown` is returned the same way. */
+        pub fn assert_healthy(ctx: Context<GetHf>, min_hf_q64: u128) -> Result<()> {
+            let obligation = load_obligation(&ctx.accounts.config, &ctx.accounts.obligation)?;
+            let inputs = obligation_inputs(&ctx.accounts.config, &obligation, &ctx.accounts.lending_market, ctx.remaining_accounts)?;
+            let breakdown = compute_hf_internal(&inputs, PriceMode::Spot)?;
+            require_min_hf(breakdown.hf_q64, min_hf_q64)?;
+            return_breakdown(&breakdown)
+        }
+
+        /* Liquidates an obligation through klend, but only if its HF re-derived here is below 1.0.
+        - Remaining accounts: the obligation's reserves, as in `get_hf`.
+        - Repays up to `liquidity_amount` from `user_source_liquidity` and receives the seized collateral,
+          redeemed to `user_destination_liquidity`; reverts if less than `min_collateral_received` arrives.
+        - klend's `refresh_reserve`/`refresh_obligation` instructions must precede this one in the transaction. */
+        pub fn liquidate_if_unhealthy<'info>(
+            ctx: Context<'_, '_, '_, 'info, LiquidateIfUnhealthy<'info>>,
+            liquidity_amount: u64,
+            min_collateral_received: u64,
+        ) -> Result<()> {
+            ctx.accounts.config.require_not_paused()?;
+            let obligation = load_obligation(&ctx.accounts.config, &ctx.accounts.obligation)?;
+            require_keys_eq!(
+                obligation.lending_market,
+                ctx.accounts.lending_market.key(),
+                HfError::LendingMarketMismatch
+            );
+            let inputs = obligation_inputs(&ctx.accounts.config, &obligation, &ctx.accounts.lending_market, ctx.remaining_accounts)?;
+            let hf_q64 = compute_hf_internal(&inputs, PriceMode::Spot)?.hf_q64;
+            require!(hf_q64 < Q64::ONE.to_bits(), HfError::ObligationHealthy);
+
+            let a = &ctx.accounts;
+            let accounts = klend::LiquidateObligationAccounts {
+                liquidator: a.liquidator.to_account_info(),
+                obligation: a.obligation.to_account_info(),
+                lending_market: a.lending_market.to_account_info(),
+                lending_market_authority: a.lending_market_authority.to_account_info(),
+                repay_reserve: a.repay_reserve.to_account_info(),
+                repay_reserve_liquidity_mint: a.repay_reserve_liquidity_mint.to_account_info(),
+                repay_reserve_liquidity_supply: a.repay_reserve_liquidity_supply.to_account_info(),
+                withdraw_reserve: a.withdraw_reserve.to_account_info(),
+                withdraw_reserve_liquidity_mint: a.withdraw_reserve_liquidity_mint.to_account_info(),
+                withdraw_reserve_collateral_mint: a.withdraw_reserve_collateral_mint.to_account_info(),
+                withdraw_reserve_collateral_supply: a.withdraw_reserve_collateral_supply.to_account_info(),
+                withdraw_reserve_liquidity_supply: a.withdraw_reserve_liquidity_supply.to_account_info(),
+                withdraw_reserve_liquidity_fee_receiver: a.withdraw_reserve_liquidity_fee_receiver.to_account_info(),
+                user_source_liquidity: a.user_source_liquidity.to_account_info(),
+                user_destination_collateral: a.user_destination_collateral.to_account_info(),
+                user_destination_liquidity: a.user_destination_liquidity.to_account_info(),
+                collateral_token_program: a.collateral_token_program.to_account_info(),
+                repay_liquidity_token_program: a.repay_liquidity_token_program.to_account_info(),
+                withdraw_liquidity_token_program: a.withdraw_liquidity_token_program.to_account_info(),
+                instruction_sysvar_account: a.instruction_sysvar_account.to_account_info(),
+            };
+            klend::liquidate_obligation_and_redeem_reserve_collateral(
+                &a.klend_program.to_account_info(),
+                &accounts,
+                liquidity_amount,
+                min_collateral_received,
+                0,
+            )?;
+
+            emit!(ObligationLiquidated {
+                obligation: a.obligation.key(),
+                liquidator: a.liquidator.key(),
+                hf_q64,
+                liquidity_amount,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /* Quotes the largest liquidation of an obligation repaying `repay_reserve` and seizing `withdraw_reserve`.
+        - Applies the market's close factor and the withdraw reserve's liquidation bonus, capped by the obligation's
+          elevation group (see `liquidation::quote_liquidation`).
+        - Remaining accounts: the obligation's reserves, as in `get_hf`.
+        - Read-only; the Borsh-encoded `LiquidationQuote` is written with `set_return_data`. */
+        pub fn quote_liquidation(ctx: Context<QuoteLiquidation>, repay_reserve: Pubkey, withdraw_reserve: Pubkey) -> Result<()> {
+            let obligation = load_obligation(&ctx.accounts.config, &ctx.accounts.obligation)?;
+            require_keys_eq!(
+                obligation.lending_market,
+                ctx.accounts.lending_market.key(),
+                HfError::LendingMarketMismatch
+            );
+            let klend_program = &ctx.accounts.config.klend_program;
+            let market = klend::LendingMarket::load(&ctx.accounts.lending_market, klend_program)?;
+
+            let repay_index = obligation
+                .borrows
+                .iter()
+                .position(|b| b.reserve == repay_reserve)
+                .ok_or(HfError::LiquidationReserveMismatch)?;
+            let withdraw_index = obligation
+                .deposits
+                .iter()
+                .position(|d| d.reserve == withdraw_reserve)
+                .ok_or(HfError::LiquidationReserveMismatch)?;
+
+            let inputs = obligation_inputs(&ctx.accounts.config, &obligation, &ctx.accounts.lending_market, ctx.remaining_accounts)?;
+            // `obligation_inputs` has already matched every reserve account against the obligation
+            let withdraw = klend::Reserve::load(&ctx.remaining_accounts[withdraw_index], klend_program)?;
+            let group = market.elevation_group(obligation.elevation_group)?;
+            let params = LiquidationParams::new(&market, &withdraw, group.as_ref());
+            let quote = liquidation::quote_liquidation(&inputs, repay_index, withdraw_index, &params)?;
+
+            set_return_data(&quote.try_to_vec()?);
+            Ok(())
+        }
+
+        /* Creates the program's `Config`; only the program's upgrade authority can call it, once.
+        - The signer becomes the config admin. */
+        pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+            params.validate()?;
+            let config = &mut ctx.accounts.config;
+            config.admin = ctx.accounts.admin.key();
+            config.pending_admin = None;
+            config.paused = false;
+            config.apply(params);
+            Ok(())
+        }
+
+        /* Replaces every admin-controlled setting of the `Config`; the pause flag is only changed by `set_paused`. */
+        pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+            params.validate()?;
+            ctx.accounts.config.apply(params);
+            Ok(())
+        }
+
+        /* Emergency switch: while paused, HF-storing, keeper and liquidation instructions fail with `HfError::Paused`.
+        - Read-only instructions (`get_hf`, `assert_healthy`, `quote_liquidation`, `get_hf_history`) and account
+          cleanup keep working. */
+        pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+            ctx.accounts.config.paused = paused;
+            emit!(PauseChanged {
+                admin: ctx.accounts.admin.key(),
+                paused,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+            Ok(())
+        }
+
+        /* First step of an admin handover: nominates `new_admin`, who must then call `accept_admin`.
+        - Nominating again replaces the pending admin; the current admin stays in control until accepted. */
+        pub fn transfer_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+            ctx.accounts.config.pending_admin = Some(new_admin);
+            Ok(())
+        }
+
+        /* Second step of an admin handover, signed by the pending admin. */
+        pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+            let config = &mut ctx.accounts.config;
+            config.admin = ctx.accounts.new_admin.key();
+            config.pending_admin = None;
+            Ok(())
+        }
+
+        /* Moves a legacy per-user `HfState` (seeds `["hf", user]`) to the per-obligation PDA
+        `["hf", lending_market, obligation]` and closes the legacy account, refunding its rent.
+        - The obligation must belong to the user; its last HF carries over unchanged
+          (legacy accounts have no conservative HF, so it starts equal to the spot HF). */
+        pub fn migrate_legacy_hf_state(ctx: Context<MigrateLegacyHfState>) -> Result<()> {
+            let a = &ctx.accounts;
+            load_user_obligation(&a.config, &a.obligation, &a.user.key(), &a.lending_market.key())?;
+            let legacy = LegacyHfState::load(&a.legacy_hf_state)?;
+            require_keys_eq!(legacy.user, a.user.key(), HfError::InvalidLegacyHfState);
+
+            let state = &mut ctx.accounts.hf_state;
+            state.version = HF_STATE_VERSION;
+            state.last_hf_q64 = legacy.last_hf_q64;
+            state.last_conservative_hf_q64 = legacy.last_hf_q64;
+            state.user = legacy.user;
+            state.last_update_slot = legacy.last_update_slot;
+            state.market = ctx.accounts.lending_market.key();
+            state.obligation = ctx.accounts.obligation.key();
+
+            close_account(&ctx.accounts.legacy_hf_state, &ctx.accounts.user)
+        }
+
+        /* Closes the signer's legacy per-user `HfState` without migrating it, refunding its rent to the signer.
+        - For users whose obligation no longer exists; legacy accounts do not deserialize as `HfState`,
+          so `close_hf_state` cannot close them. */
+        pub fn close_legacy_hf_state(ctx: Context<CloseLegacyHfState>) -> Result<()> {
+            let legacy = LegacyHfState::load(&ctx.accounts.legacy_hf_state)?;
+            require_keys_eq!(legacy.user, ctx.accounts.user.key(), HfError::InvalidLegacyHfState);
+            close_account(&ctx.accounts.legacy_hf_state, &ctx.accounts.user)
+        }
+
+        /* Upgrades an `HfState` written with an older layout to `HF_STATE_VERSION` in place.
+        - Permissionless: `payer` funds the extra rent of the larger account; current accounts are left untouched.
+        - Until migrated, older accounts fail to deserialize in every other instruction. */
+        pub fn migrate_hf_state(ctx: Context<MigrateHfState>) -> Result<()> {
+            let info = ctx.accounts.hf_state.to_account_info();
+            require_keys_eq!(*info.owner, crate::ID, HfError::InvalidHfStateVersion);
+            let v1 = {
+                let data = info.try_borrow_data()?;
+                require!(
+                    data.len() >= 8 && data[..8] == *HfState::DISCRIMINATOR,
+                    HfError::InvalidHfStateVersion
+                );
+                if data.len() == HfState::SPACE {
+                    return Ok(());
+                }
+                HfStateV1::decode(&data)?
+            };
+
+            let shortfall = Rent::get()?.minimum_balance(HfState::SPACE).saturating_sub(info.lamports());
+            if shortfall > 0 {
+                let cpi_accounts = anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: info.clone(),
+                };
+                let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+                anchor_lang::system_program::transfer(cpi_ctx, shortfall)?;
+            }
+            info.resize(HfState::SPACE)?;
+
+            let mut data = info.try_borrow_mut_data()?;
+            v1.upgrade().try_serialize(&mut &mut data[..])
+        }
+
+        /* Closes one of the signer's `HfState` accounts, refunding its rent to the signer. */
+        pub fn close_hf_state(_ctx: Context<CloseHfState>) -> Result<()> {
+            Ok(())
+        }
+
+        /* Closes, in bulk, every `HfHistory` passed as a (writable) remaining account.
+        - Every remaining account must be an `HfHistory` of the signer; all rent goes to the signer.
+        - `HfState` accounts are closed one at a time with `close_hf_state`. */
+        pub fn close_hf_accounts<'info>(ctx: Context<'_, '_, 'info, 'info, CloseHfAccounts<'info>>) -> Result<()> {
+            let user = ctx.accounts.user.to_account_info();
+            for info in ctx.remaining_accounts {
+                let history = AccountLoader::<HfHistory>::try_from(info)?;
+                require_keys_eq!(history.load()?.user, user.key(), HfError::HfStateOwnerMismatch);
+                require!(info.is_writable, ErrorCode::AccountNotMutable);
+                close_account(info, &user)?;
+            }
+            Ok(())
+        }
+
+        /* Returns a window of an obligation's HF history: samples taken at or after `from_slot`, oldest first.
+        - At most `max_samples` (capped at `history::MAX_HISTORY_WINDOW`) of the newest matching samples.
+        - Read-only; the Borsh-encoded `Vec<HfSample>` is written with `set_return_data`. */
+        pub fn get_hf_history(ctx: Context<GetHfHistory>, from_slot: u64, max_samples: u8) -> Result<()> {
+            let history = ctx.accounts.hf_history.load()?;
+            let window = history.window(from_slot, (max_samples as usize).min(MAX_HISTORY_WINDOW));
+            set_return_data(&window.try_to_vec()?);
+            Ok(())
+        }
+    }
+}
+
+pub use program_module::*;
+
+/* Builds HF inputs from an obligation, pricing each asset at its reserve's klend price.
+- Reserves whose price was not refreshed within `config.max_price_age_slots`, or that klend considers stale,
+  are rejected.
+- Obligations in an elevation group use the group's liquidation threshold (read from `lending_market`).
+- Debt includes the interest accrued since the obligation's last refresh, projected to the current slot. */
+fn obligation_inputs(
+    config: &Config,
+    obligation: &klend::Obligation,
+    lending_market: &AccountInfo,
+    reserves: &[AccountInfo],
+) -> Result<HfInputs> {
+    let current_slot = Clock::get()?.slot;
+    let group = load_elevation_group(config, obligation, lending_market)?;
+    // `market_price_sf` converts to Q64.64 exactly, so there is nothing to round.
+    klend::hf_inputs_from_obligation(obligation, reserves, &config.klend_program, group.as_ref(), current_slot, |_, reserve, _| {
+        reserve.price_q64(current_slot, config.max_price_age_slots).map(OraclePrice::spot)
+    })
+}
+
+/* Reads the obligation's elevation group from its lending market; the market is only loaded for eMode obligations. */
+fn load_elevation_group(
+    config: &Config,
+    obligation: &klend::Obligation,
+    lending_market: &AccountInfo,
+) -> Result<Option<klend::ElevationGroup>> {
+    require_keys_eq!(lending_market.key(), obligation.lending_market, HfError::LendingMarketMismatch);
+    if obligation.elevation_group == 0 {
+        return Ok(None);
+    }
+    klend::LendingMarket::load(lending_market, &config.klend_program)?.elevation_group(obligation.elevation_group)
+}
+
+/* Rejects an HF below the caller's minimum; an HF equal to the minimum passes. */
+fn require_min_hf(hf_q64: u128, min_hf_q64: u128) -> Result<()> {
+    if hf_q64 < min_hf_q64 {
+        msg!("HF {} is below the required minimum {}", Q64::from_bits(hf_q64), Q64::from_bits(min_hf_q64));
+        return err!(HfError::Unhealthy);
+    }
+    Ok(())
+}
+
+/* Writes a Borsh-encoded `HfBreakdown` as the instruction's return data. */
+fn return_breakdown(breakdown: &HfBreakdown) -> Result<()> {
+    let return_data = breakdown.try_to_vec()?;
+    require!(return_data.len() <= MAX_RETURN_DATA, HfError::ReturnDataTooLarge);
+    set_return_data(&return_data);
+    Ok(())
+}
 
-        Ok(())
+/* Loads an obligation of the configured klend program and checks its market and reserves are allowed. */
+fn load_obligation(config: &Config, info: &AccountInfo) -> Result<klend::Obligation> {
+    let obligation = klend::Obligation::load(info, &config.klend_program)?;
+    config.require_market(&obligation.lending_market)?;
+    let deposit_reserves = obligation.deposits.iter().map(|d| &d.reserve);
+    for reserve in deposit_reserves.chain(obligation.borrows.iter().map(|b| &b.reserve)) {
+        config.require_reserve(reserve)?;
     }
+
+    Ok(obligation)
+}
+
+/* Loads an obligation as `load_obligation` does and checks it belongs to `user` and `lending_market`. */
+fn load_user_obligation(
+    config: &Config,
+    info: &AccountInfo,
+    user: &Pubkey,
+    lending_market: &Pubkey,
+) -> Result<klend::Obligation> {
+    let obligation = load_obligation(config, info)?;
+    require_keys_eq!(obligation.owner, *user, HfError::ObligationOwnerMismatch);
+    require_keys_eq!(obligation.lending_market, *lending_market, HfError::LendingMarketMismatch);
+
+    Ok(obligation)
+}
+
+/* Closes a program-owned account, sending its lamports to `destination`. */
+fn close_account<'info>(info: &AccountInfo<'info>, destination: &AccountInfo<'info>) -> Result<()> {
+    let lamports = info.lamports();
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(HfError::MathOverflow)?;
+    **info.try_borrow_mut_lamports()? = 0;
+    info.assign(&anchor_lang::system_program::ID);
+    info.resize(0)?;
+
+    Ok(())
 }
 
-/* Context for computing and storing a user’s HF. */
+/* Identifies the `HfState` being written: its owner, market and obligation. */
+struct HfStateKey {
+    user: Pubkey,
+    market: Pubkey,
+    obligation: Pubkey,
+}
+
+impl HfStateKey {
+    fn new(user: &AccountInfo, market: &AccountInfo, obligation: &AccountInfo) -> Self {
+        Self {
+            user: user.key(),
+            market: market.key(),
+            obligation: obligation.key(),
+        }
+    }
+}
+
+/* Persists a freshly computed HF and appends it to the obligation's history,
+returns its breakdown via return data and emits `HealthFactorComputed`.
+- Without a conservative HF in the breakdown, `last_conservative_hf_q64` repeats the spot HF.
+- `history` is `None` for unverified HFs, which must not reach `HfHistory`. */
+fn store_hf(
+    state: &mut Account<'_, HfState>,
+    history: Option<&AccountLoader<'_, HfHistory>>,
+    key: HfStateKey,
+    breakdown: HfBreakdown,
+    pricing: HfPricing,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let user = key.user;
+    state.version = HF_STATE_VERSION;
+    state.last_hf_q64 = breakdown.hf_q64;
+    state.last_conservative_hf_q64 = breakdown.conservative_hf_q64.unwrap_or(breakdown.hf_q64);
+    state.user = user;
+    state.last_update_slot = clock.slot;
+    state.market = key.market;
+    state.obligation = key.obligation;
+    state.pricing = pricing;
+
+    if let Some(history) = history {
+        // `init_if_needed` leaves a new zero-copy account without its discriminator until the instruction exits
+        let is_new = history.to_account_info().try_borrow_data()?[..8] == [0u8; 8];
+        let mut samples = if is_new { history.load_init()? } else { history.load_mut()? };
+        samples.user = user;
+        samples.market = key.market;
+        samples.obligation = key.obligation;
+        samples.push(HfSample {
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+            hf_q64: breakdown.hf_q64,
+            total_collateral_q64: breakdown.total_collateral_value_q64,
+            total_debt_q64: breakdown.total_debt_value_q64,
+        });
+    }
+
+    return_breakdown(&breakdown)?;
+
+    emit!(HealthFactorComputed {
+        user,
+        obligation: key.obligation,
+        hf_q64: breakdown.hf_q64,
+        borrow_hf_q64: breakdown.borrow_hf_q64,
+        conservative_hf_q64: breakdown.conservative_hf_q64,
+        pricing,
+        timestamp: clock.unix_timestamp,
+        total_collateral_value_q64: breakdown.total_collateral_value_q64,
+        total_weighted_collateral_q64: breakdown.total_weighted_collateral_q64,
+        total_borrow_limit_q64: breakdown.total_borrow_limit_q64,
+        total_debt_value_q64: breakdown.total_debt_value_q64,
+        total_weighted_debt_q64: breakdown.total_weighted_debt_q64,
+        collaterals: breakdown.collaterals,
+        debts: breakdown.debts,
+    });
+
+    Ok(())
+}
+
+/* Context for computing and storing a user’s HF from caller-supplied inputs. */
 #[derive(Accounts)]
 pub struct ComputeHf<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    /// CHECK: owner program, discriminator, owner and market are verified in `load_user_obligation`.
+    pub obligation: UncheckedAccount<'info>,
+
+    /// CHECK: must be the obligation's lending market; verified in `load_user_obligation`.
+    pub lending_market: UncheckedAccount<'info>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = HfState::SPACE,
+        seeds = [b"hf", lending_market.key().as_ref(), obligation.key().as_ref()],
+        bump
+    )]
+    pub hf_state: Account<'info, HfState>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/* Context for computing a user’s HF from their Kamino obligation. */
+#[derive(Accounts)]
+pub struct ComputeHfFromObligation<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: owner program, discriminator, owner and market are verified in `load_user_obligation`.
+    pub obligation: UncheckedAccount<'info>,
+
+    /// CHECK: must be the obligation's lending market; verified in `load_user_obligation`.
+    pub lending_market: UncheckedAccount<'info>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+
     #[account(
         init_if_needed,
         payer = user,
-        space = 8 + HfState::INIT_SPACE,
-        seeds = [b"hf", user.key().as_ref()],
+        space = HfState::SPACE,
+        seeds = [b"hf", lending_market.key().as_ref(), obligation.key().as_ref()],
         bump
     )]
     pub hf_state: Account<'info, HfState>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + std::mem::size_of::<HfHistory>(),
+        seeds = [b"hf_history", lending_market.key().as_ref(), obligation.key().as_ref()],
+        bump
+    )]
+    pub hf_history: AccountLoader<'info, HfHistory>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/* Context for a keeper refreshing the stored HF of any obligation. */
+#[derive(Accounts)]
+pub struct RefreshHf<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    /// CHECK: must be the obligation's owner; verified in `load_user_obligation`.
+    pub owner: UncheckedAccount<'info>,
+
+    /// CHECK: owner program, discriminator, owner and market are verified in `load_user_obligation`.
+    pub obligation: UncheckedAccount<'info>,
+
+    /// CHECK: must be the obligation's lending market; verified in `load_user_obligation`.
+    pub lending_market: UncheckedAccount<'info>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = HfState::SPACE,
+        seeds = [b"hf", lending_market.key().as_ref(), obligation.key().as_ref()],
+        bump
+    )]
+    pub hf_state: Account<'info, HfState>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = 8 + std::mem::size_of::<HfHistory>(),
+        seeds = [b"hf_history", lending_market.key().as_ref(), obligation.key().as_ref()],
+        bump
+    )]
+    pub hf_history: AccountLoader<'info, HfHistory>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/* Context for read-only HF checks on any obligation (`get_hf`, `assert_healthy`). */
+#[derive(Accounts)]
+pub struct GetHf<'info> {
+    /// CHECK: owner program, discriminator and market are verified in `load_obligation`.
+    pub obligation: UncheckedAccount<'info>,
+
+    /// CHECK: must be the obligation's lending market; verified in `load_elevation_group`.
+    pub lending_market: UncheckedAccount<'info>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+}
+
+/* Context for `liquidate_if_unhealthy`: klend's liquidation accounts plus the klend program.
+- Everything except the obligation's lending market is validated by klend itself. */
+#[derive(Accounts)]
+pub struct LiquidateIfUnhealthy<'info> {
+    pub liquidator: Signer<'info>,
+
+    /// CHECK: owner program, discriminator and market are verified in `load_obligation`.
+    #[account(mut)]
+    pub obligation: UncheckedAccount<'info>,
+
+    /// CHECK: must be the obligation's lending market; validated by klend.
+    pub lending_market: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    pub lending_market_authority: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    #[account(mut)]
+    pub repay_reserve: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    pub repay_reserve_liquidity_mint: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    #[account(mut)]
+    pub repay_reserve_liquidity_supply: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    #[account(mut)]
+    pub withdraw_reserve: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    pub withdraw_reserve_liquidity_mint: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    #[account(mut)]
+    pub withdraw_reserve_collateral_mint: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    #[account(mut)]
+    pub withdraw_reserve_collateral_supply: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    #[account(mut)]
+    pub withdraw_reserve_liquidity_supply: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    #[account(mut)]
+    pub withdraw_reserve_liquidity_fee_receiver: UncheckedAccount<'info>,
+
+    /// CHECK: liquidator's token account for the repaid liquidity; validated by klend.
+    #[account(mut)]
+    pub user_source_liquidity: UncheckedAccount<'info>,
+
+    /// CHECK: liquidator's token account for the seized cTokens; validated by klend.
+    #[account(mut)]
+    pub user_destination_collateral: UncheckedAccount<'info>,
+
+    /// CHECK: liquidator's token account for the redeemed collateral liquidity; validated by klend.
+    #[account(mut)]
+    pub user_destination_liquidity: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    pub collateral_token_program: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    pub repay_liquidity_token_program: UncheckedAccount<'info>,
+
+    /// CHECK: validated by klend.
+    pub withdraw_liquidity_token_program: UncheckedAccount<'info>,
+
+    /// CHECK: address is checked.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar_account: UncheckedAccount<'info>,
+
+    /// CHECK: address is checked against the config.
+    #[account(address = config.klend_program @ ErrorCode::InvalidProgramId)]
+    pub klend_program: UncheckedAccount<'info>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+}
+
+/* Context for `quote_liquidation`. */
+#[derive(Accounts)]
+pub struct QuoteLiquidation<'info> {
+    /// CHECK: owner program, discriminator and market are verified in `load_obligation`.
+    pub obligation: UncheckedAccount<'info>,
+
+    /// CHECK: owner program and discriminator are verified in `klend::LendingMarket::load`.
+    pub lending_market: UncheckedAccount<'info>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+}
+
+/* Context for moving a legacy per-user `HfState` to its per-obligation PDA. */
+#[derive(Accounts)]
+pub struct MigrateLegacyHfState<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: decoded by `LegacyHfState::load` and closed by the instruction.
+    #[account(mut, seeds = [b"hf", user.key().as_ref()], bump)]
+    pub legacy_hf_state: UncheckedAccount<'info>,
+
+    /// CHECK: owner program, discriminator, owner and market are verified in `load_user_obligation`.
+    pub obligation: UncheckedAccount<'info>,
+
+    /// CHECK: must be the obligation's lending market; verified in `load_user_obligation`.
+    pub lending_market: UncheckedAccount<'info>,
+
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = user,
+        space = HfState::SPACE,
+        seeds = [b"hf", lending_market.key().as_ref(), obligation.key().as_ref()],
+        bump
+    )]
+    pub hf_state: Account<'info, HfState>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/* Context for closing a legacy per-user `HfState` without migrating it. */
+#[derive(Accounts)]
+pub struct CloseLegacyHfState<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: decoded by `LegacyHfState::load` and closed by the instruction.
+    #[account(mut, seeds = [b"hf", user.key().as_ref()], bump)]
+    pub legacy_hf_state: UncheckedAccount<'info>,
+}
+
+/* Context for creating the `Config`, signed by the program's upgrade authority. */
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(init, payer = admin, space = Config::SPACE, seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ HfError::Unauthorized)]
+    pub program: Program<'info, crate::program::KaminoIntegration>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ HfError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/* Context for admin-only `Config` changes (`update_config`, `set_paused`, `transfer_admin`). */
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(mut, seeds = [CONFIG_SEED], bump, has_one = admin @ HfError::Unauthorized)]
+    pub config: Account<'info, Config>,
+}
+
+/* Context for the pending admin accepting an admin handover. */
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub new_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump,
+        constraint = config.pending_admin == Some(new_admin.key()) @ HfError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+}
+
+/* Context for upgrading an `HfState` to the current layout. */
+#[derive(Accounts)]
+pub struct MigrateHfState<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: owner, discriminator and layout are verified in `migrate_hf_state`.
+    #[account(mut)]
+    pub hf_state: UncheckedAccount<'info>,
+
     pub system_program: Program<'info, System>,
 }
 
-/* Account for storing a user’s HF state. */
+/* Context for closing one of the signer's `HfState` accounts. */
+#[derive(Accounts)]
+pub struct CloseHfState<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, close = user, constraint = hf_state.user == user.key() @ HfError::HfStateOwnerMismatch)]
+    pub hf_state: Account<'info, HfState>,
+}
+
+/* Context for closing the signer's `HfHistory` accounts, passed as remaining accounts. */
+#[derive(Accounts)]
+pub struct CloseHfAccounts<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+/* Context for reading any obligation's HF history. */
+#[derive(Accounts)]
+pub struct GetHfHistory<'info> {
+    pub hf_history: AccountLoader<'info, HfHistory>,
+}
+
+/* Current `HfState` layout version; bump it (and add an upgrade path) whenever the layout changes. */
+pub const HF_STATE_VERSION: u8 = 2;
+
+/* Account for storing the HF of one obligation, seeded `["hf", market, obligation]`.
+- Fields are only ever appended, so every older layout is a prefix of this one.
+- New fields should be carved out of `reserved`, so most additions need no migration. */
 #[account]
 #[derive(InitSpace)]
 pub struct HfState {
     pub last_hf_q64: u128,
     pub user: Pubkey,
     pub last_update_slot: u64,
+    /// Pessimistic HF when `pricing` is `ConservativeOraclePrices`; otherwise a copy of `last_hf_q64`.
+    pub last_conservative_hf_q64: u128,
+    pub market: Pubkey,
+    pub obligation: Pubkey,
+    pub version: u8,
+    pub pricing: HfPricing,
+    pub reserved: [u8; 127],
+}
+
+impl HfState {
+    pub const SPACE: usize = 8 + HfState::INIT_SPACE;
+}
+
+/* How the HF stored in an `HfState` was priced, so readers can tell the compute paths apart. */
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HfPricing {
+    /// Not recorded: the account was migrated and has not been recomputed since.
+    #[default]
+    Unknown,
+    /// klend reserve `market_price_sf` (`compute_hf_from_obligation`, `refresh_hf`).
+    ReservePrices,
+    /// Oracle feeds picked per asset by the caller, within the `Config` age and confidence caps
+    /// (`compute_hf_from_oracles`).
+    OraclePrices,
+    /// Oracle feeds as for `OraclePrices`, in conservative mode: only then is `last_conservative_hf_q64`
+    /// a pessimistic bound rather than a copy of `last_hf_q64`.
+    ConservativeOraclePrices,
+    /// Caller-supplied `ComputeArgs` (`compute_hf`): not verified, so not to be relied upon.
+    CallerInputs,
+}
+
+/* Version 1 of `HfState`: keyed by market and obligation, without version or reserved space. */
+#[derive(AnchorDeserialize, Clone, Debug)]
+pub struct HfStateV1 {
+    pub last_hf_q64: u128,
+    pub user: Pubkey,
+    pub last_update_slot: u64,
+    pub last_conservative_hf_q64: u128,
+    pub market: Pubkey,
+    pub obligation: Pubkey,
+}
+
+impl HfStateV1 {
+    const LEN: usize = 16 + 32 + 8 + 16 + 32 + 32;
+
+    /* Decodes v1 account data (discriminator included). */
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        require!(data.len() == 8 + Self::LEN, HfError::InvalidHfStateVersion);
+        Self::deserialize(&mut &data[8..]).map_err(|_| error!(HfError::InvalidHfStateVersion))
+    }
+
+    pub fn upgrade(self) -> HfState {
+        HfState {
+            last_hf_q64: self.last_hf_q64,
+            user: self.user,
+            last_update_slot: self.last_update_slot,
+            last_conservative_hf_q64: self.last_conservative_hf_q64,
+            market: self.market,
+            obligation: self.obligation,
+            version: HF_STATE_VERSION,
+            pricing: HfPricing::Unknown,
+            reserved: [0; 127],
+        }
+    }
+}
+
+/* The original per-user `HfState` layout (seeds `["hf", user]`), a prefix of the current one. */
+#[derive(AnchorDeserialize, Clone, Debug)]
+pub struct LegacyHfState {
+    pub last_hf_q64: u128,
+    pub user: Pubkey,
+    pub last_update_slot: u64,
+}
+
+impl LegacyHfState {
+    const LEN: usize = 16 + 32 + 8;
+
+    /* Decodes a legacy account after checking its owner, size and `HfState` discriminator. */
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require_keys_eq!(*info.owner, crate::ID, HfError::InvalidLegacyHfState);
+        let data = info.try_borrow_data()?;
+        require!(
+            data.len() == 8 + Self::LEN && data[..8] == *HfState::DISCRIMINATOR,
+            HfError::InvalidLegacyHfState
+        );
+
+        Self::deserialize(&mut &data[8..]).map_err(|_| error!(HfError::InvalidLegacyHfState))
+    }
 }
 
 /* Input arguments for computing HF. */
@@ -64,155 +1002,309 @@ pub struct ComputeArgs {
     pub debts: Vec<DebtInput>,
 }
 
-/* Input arguments for collateral. */
+/* Input arguments for collateral.
+- `max_ltv_bps` (klend `loan_to_value_pct`) caps new borrows; `liq_threshold_bps`
+  (klend `liquidation_threshold_pct`) decides liquidation eligibility, and must not be below it.
+- `borrow_factor_bps` is kept for backward compatibility only (it divides this collateral's value);
+  borrow factors belong to the borrowed reserve, see `DebtInput::borrow_factor_bps`. */
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct CollateralInput {
     pub amount: u64,
     pub decimals: u8,
     pub price_e8: i64,
+    pub max_ltv_bps: u16,
     pub liq_threshold_bps: u16,
     pub borrow_factor_bps: u16,
 }
 
-/* Input arguments for debt. */
+/* Input arguments for debt.
+- `borrow_factor_bps` is the borrowed reserve's borrow factor (klend `borrow_factor_pct` * 100),
+  at least 10_000; 0 means no borrow factor (100%). */
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct DebtInput {
     pub amount: u64,
     pub decimals: u8,
     pub price_e8: i64,
+    pub borrow_factor_bps: u32,
+}
+
+impl ComputeArgs {
+    /* Validates caller-supplied prices and converts them to Q64.64 positions.
+    - Collateral prices round down and debt prices round up, like every other HF term. */
+    fn to_inputs(&self) -> Result<HfInputs> {
+        let mut collaterals = Vec::with_capacity(self.collaterals.len());
+        for c in self.collaterals.iter() {
+            let price_q64 = q64_from_price_e8(c.price_e8, Rounding::Floor)?;
+            collaterals.push(CollateralPosition {
+                amount: c.amount,
+                decimals: c.decimals,
+                price_q64,
+                conservative_price_q64: price_q64,
+                max_ltv_bps: c.max_ltv_bps,
+                liq_threshold_bps: c.liq_threshold_bps,
+                borrow_factor_bps: c.borrow_factor_bps,
+            });
+        }
+
+        let mut debts = Vec::with_capacity(self.debts.len());
+        for d in self.debts.iter() {
+            let price_q64 = q64_from_price_e8(d.price_e8, Rounding::Ceil)?;
+            debts.push(DebtPosition {
+                amount: d.amount,
+                decimals: d.decimals,
+                price_q64,
+                conservative_price_q64: price_q64,
+                borrow_factor_bps: d.borrow_factor_bps,
+            });
+        }
+
+        Ok(HfInputs { collaterals, debts })
+    }
+}
+
+/* Which price of each position `compute_hf_internal` should use. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriceMode {
+    Spot,
+    Conservative,
+}
+
+/* Collateral position with its prices already in Q64.64.
+- `conservative_price_q64` is the lower bound of the oracle price (equal to spot when unknown). */
+#[derive(Clone, Debug)]
+pub struct CollateralPosition {
+    pub amount: u64,
+    pub decimals: u8,
+    pub price_q64: u128,
+    pub conservative_price_q64: u128,
+    pub max_ltv_bps: u16,
+    pub liq_threshold_bps: u16,
+    pub borrow_factor_bps: u16,
+}
+
+/* Debt position with its prices already in Q64.64.
+- `conservative_price_q64` is the upper bound of the oracle price (equal to spot when unknown). */
+#[derive(Clone, Debug)]
+pub struct DebtPosition {
+    pub amount: u64,
+    pub decimals: u8,
+    pub price_q64: u128,
+    pub conservative_price_q64: u128,
+    pub borrow_factor_bps: u32,
+}
+
+/* Normalized inputs for `compute_hf_internal`, built from `ComputeArgs` or klend accounts. */
+#[derive(Clone, Debug)]
+pub struct HfInputs {
+    pub collaterals: Vec<CollateralPosition>,
+    pub debts: Vec<DebtPosition>,
+}
+
+/* One asset's contribution to the HF, all values in Q64.64.
+- `value_q64` is the USD value; `weighted_value_q64` applies the liquidation threshold to
+  collaterals and the borrow factor to debts.
+- `share_q64` is the weighted value as a fraction of its side's weighted total. */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AssetBreakdown {
+    pub amount_q64: u128,
+    pub value_q64: u128,
+    pub weighted_value_q64: u128,
+    pub share_q64: u128,
+}
+
+/* Result of `compute_hf_internal`: the HF plus the per-asset terms it was built from, in input order.
+- Returned via `set_return_data` (Borsh) and carried by `HealthFactorComputed`.
+- `conservative_hf_q64` is only set by `compute_hf_from_oracles` in conservative mode; the totals and
+  per-asset entries are always at spot prices. */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HfBreakdown {
+    pub hf_q64: u128,
+    pub borrow_hf_q64: u128,
+    pub conservative_hf_q64: Option<u128>,
+    pub total_collateral_value_q64: u128,
+    pub total_weighted_collateral_q64: u128,
+    pub total_borrow_limit_q64: u128,
+    pub total_debt_value_q64: u128,
+    pub total_weighted_debt_q64: u128,
+    pub collaterals: Vec<AssetBreakdown>,
+    pub debts: Vec<AssetBreakdown>,
+}
+
+impl HfBreakdown {
+    /* Decodes an `HfBreakdown` from this program's return data.
+    - Transaction metadata and simulations drop trailing zero bytes, so they are restored first;
+      data read on-chain with `get_return_data` is complete and decodes unchanged. */
+    pub fn from_return_data(data: &[u8]) -> Result<Self> {
+        require!(data.len() <= MAX_RETURN_DATA, HfError::InvalidReturnData);
+        let mut padded = [0u8; MAX_RETURN_DATA];
+        padded[..data.len()].copy_from_slice(data);
+        HfBreakdown::deserialize(&mut &padded[..]).map_err(|_| error!(HfError::InvalidReturnData))
+    }
 }
 
 /* Computes the Health Factor (HF) for a given set of collateral and debt assets. */
 ///
 /// ### Formula
-/// HF = (Σ (collateral_i * price_i * liq_threshold_i / borrow_factor_i))
-///       / (Σ (debt_j * price_j))
+/// HF = (Σ (collateral_i * price_i * liq_threshold_i))
+///       / (Σ (debt_j * price_j * borrow_factor_j))
 ///
 /// ### How It Works
-/// - Converts all token amounts to **Q64.64 fixed-point precision**.
-/// - Collateral values are adjusted by their liquidation thresholds and optional borrow factors.
-/// - Debt values are normalized by token decimals and multiplied by oracle price.
-/// - Uses `mul_div_q64`, `q64_mul`, and `q64_div` to safely perform high-precision arithmetic.
-/// - Returns:
+/// - Converts all token amounts to **Q64.64 fixed-point precision** (prices arrive already in Q64.64).
+/// - Collateral values are adjusted by their liquidation thresholds (and the legacy collateral borrow factor).
+/// - Debt values are normalized by token decimals, multiplied by oracle price and scaled up by the
+///   borrowed reserve's borrow factor, like klend's `borrow_factor_adjusted_debt_value`.
+/// - `PriceMode::Conservative` values collateral and debt at their pessimistic price bounds.
+/// - Uses the `hf_math::Q64` checked operations to safely perform high-precision arithmetic.
+/// - Rounds against the borrower like klend: collateral terms round down, debt terms round up
+///   and the final division rounds down, so the result never exceeds the exact HF.
+/// - Returns an `HfBreakdown` whose `hf_q64` (liquidation HF) is:
 ///   - `u128::MAX` if total debt = 0 (infinite HF),
 ///   - Otherwise `(total_collateral / total_debt)` as a Q64.64 number.
-fn compute_hf_internal(args: &ComputeArgs) -> Result<u128> {
-    let mut total_collateral_value_q64: u128 = 0;
-    let mut total_debt_value_q64: u128 = 0;
+/// - `borrow_hf_q64` is the same ratio with collateral weighted by max LTV instead of the
+///   liquidation threshold: below 1.0 klend rejects new borrows, but the position is only
+///   liquidatable once `hf_q64` drops below 1.0.
+fn compute_hf_internal(inputs: &HfInputs, mode: PriceMode) -> Result<HfBreakdown> {
+    let mut total_collateral_value = Q64::ZERO;
+    let mut total_weighted_collateral = Q64::ZERO;
+    let mut total_borrow_limit = Q64::ZERO;
+    let mut total_debt_value = Q64::ZERO;
+    let mut total_weighted_debt = Q64::ZERO;
+    let mut collaterals = Vec::with_capacity(inputs.collaterals.len());
+    let mut debts = Vec::with_capacity(inputs.debts.len());
 
     // ---------- Collaterals ----------
-    for c in args.collaterals.iter() {
-        require!(c.price_e8 > 0, HfError::InvalidPrice);
+    for c in inputs.collaterals.iter() {
+        require!(c.price_q64 > 0, HfError::InvalidPrice);
         require!(c.decimals <= 18, HfError::InvalidDecimals);
         require!(c.liq_threshold_bps <= 10_000, HfError::InvalidLiqThreshold);
+        require!(c.max_ltv_bps <= c.liq_threshold_bps, HfError::InvalidMaxLtv);
         require!(
             c.borrow_factor_bps == 0 || 
             (c.borrow_factor_bps >= 1_000 && c.borrow_factor_bps <= 10_000),
             HfError::InvalidBorrowFactor
         );
         // normalize amount to Q64
-        let amt_norm_q64 = mul_div_q64(c.amount as u128, ONE_Q64_64, ten_pow(c.decimals))?;
-        // price to Q64 (price_e8 / 1e8)
-        let price_q64 = q64_from_price_e8(c.price_e8)?;
+        let amt_norm = Q64::from_ratio(c.amount as u128, ten_pow(c.decimals).map_err(HfError::from)?, Rounding::Floor)
+            .map_err(HfError::from)?;
+        let price = Q64::from_bits(match mode {
+            PriceMode::Spot => c.price_q64,
+            PriceMode::Conservative => c.conservative_price_q64,
+        });
         // liq threshold (bps to Q64)
-        let lt_q64 = bps_to_q64(c.liq_threshold_bps)?;
+        let lt = Q64::from_bps(c.liq_threshold_bps, Rounding::Floor);
 
         // Base collateral value = amount * price * liq_threshold
-        let mut val = q64_mul(amt_norm_q64, price_q64)?;
-        val = q64_mul(val, lt_q64)?;
+        let usd_value = amt_norm.checked_mul(price, Rounding::Floor).map_err(HfError::from)?;
+        let mut val = usd_value.checked_mul(lt, Rounding::Floor).map_err(HfError::from)?;
+
+        // Borrow limit = amount * price * max_ltv
+        let ltv = Q64::from_bps(c.max_ltv_bps, Rounding::Floor);
+        let mut borrow_limit = usd_value.checked_mul(ltv, Rounding::Floor).map_err(HfError::from)?;
 
         // Apply borrow factor if present (higher = lower effective collateral)
         if c.borrow_factor_bps > 0 {
-            let bf_q64 = bps_to_q64(c.borrow_factor_bps)?;
-            val = q64_div(val, bf_q64)?;
+            let bf = Q64::from_bps(c.borrow_factor_bps, Rounding::Ceil);
+            val = val.checked_div(bf, Rounding::Floor).map_err(HfError::from)?;
+            borrow_limit = borrow_limit.checked_div(bf, Rounding::Floor).map_err(HfError::from)?;
         }
 
         // Sum collateral values
-        total_collateral_value_q64 = total_collateral_value_q64
-            .checked_add(val)
-            .ok_or(HfError::MathOverflow)?;
+        total_collateral_value = total_collateral_value.checked_add(usd_value).map_err(HfError::from)?;
+        total_weighted_collateral = total_weighted_collateral.checked_add(val).map_err(HfError::from)?;
+        total_borrow_limit = total_borrow_limit.checked_add(borrow_limit).map_err(HfError::from)?;
+        collaterals.push(asset_breakdown(amt_norm, usd_value, val));
     }
 
     // ---------- Debts ----------
-    for d in args.debts.iter() {
-        require!(d.price_e8 > 0, HfError::InvalidPrice);
+    for d in inputs.debts.iter() {
+        require!(d.price_q64 > 0, HfError::InvalidPrice);
         require!(d.decimals <= 18, HfError::InvalidDecimals);
+        require!(
+            d.borrow_factor_bps == 0 || d.borrow_factor_bps >= 10_000,
+            HfError::InvalidBorrowFactor
+        );
 
         // normalize amount to Q64
-        let amt_norm_q64 = mul_div_q64(d.amount as u128, ONE_Q64_64, ten_pow(d.decimals))?;
-        // price to Q64 (price_e8 / 1e8)
-        let price_q64 = q64_from_price_e8(d.price_e8)?;
+        let amt_norm = Q64::from_ratio(d.amount as u128, ten_pow(d.decimals).map_err(HfError::from)?, Rounding::Ceil)
+            .map_err(HfError::from)?;
+        let price = Q64::from_bits(match mode {
+            PriceMode::Spot => d.price_q64,
+            PriceMode::Conservative => d.conservative_price_q64,
+        });
         // debt value = amount * price
-        let val = q64_mul(amt_norm_q64, price_q64)?;
+        let usd_value = amt_norm.checked_mul(price, Rounding::Ceil).map_err(HfError::from)?;
+
+        // Borrow factor adjusted value = debt value * borrow factor (higher = more effective debt)
+        let mut val = usd_value;
+        if d.borrow_factor_bps > 0 {
+            let bf = Q64::from_ratio(d.borrow_factor_bps as u128, 10_000, Rounding::Ceil).map_err(HfError::from)?;
+            val = val.checked_mul(bf, Rounding::Ceil).map_err(HfError::from)?;
+        }
 
         // Sum debt values
-        total_debt_value_q64 = total_debt_value_q64
-            .checked_add(val)
-            .ok_or(HfError::MathOverflow)?;
+        total_debt_value = total_debt_value.checked_add(usd_value).map_err(HfError::from)?;
+        total_weighted_debt = total_weighted_debt.checked_add(val).map_err(HfError::from)?;
+        debts.push(asset_breakdown(amt_norm, usd_value, val));
     }
 
-    // ---- Final HF result ----
-    if total_debt_value_q64 == 0 {
-        Ok(u128::MAX)
-    } else {
-        q64_div(total_collateral_value_q64, total_debt_value_q64)
-    }
-}
+    // ---- Shares of each side's weighted total ----
+    set_shares(&mut collaterals, total_weighted_collateral);
+    set_shares(&mut debts, total_weighted_debt);
 
-// --------------- Math Helpers ---------------
-
-/* Calculates 10^dec. */
-#[inline(always)]
-fn ten_pow(dec: u8) -> u128 {
-    10u128.pow(dec as u32)
-}
+    // ---- Final HF result ----
+    // An HF too large for Q64.64 (e.g. dust debt) saturates to the same "infinite" value as no debt.
+    let hf = |collateral: Q64| {
+        if total_weighted_debt == Q64::ZERO {
+            u128::MAX
+        } else {
+            collateral.saturating_div(total_weighted_debt, Rounding::Floor).to_bits()
+        }
+    };
 
-/* Converts basis points (bps) to Q64.64 fixed-point precision. */
-#[inline(always)]
-fn bps_to_q64(bps: u16) -> Result<u128> {
-    mul_div_q64(bps as u128, ONE_Q64_64, 10_000)
+    Ok(HfBreakdown {
+        hf_q64: hf(total_weighted_collateral),
+        borrow_hf_q64: hf(total_borrow_limit),
+        conservative_hf_q64: None,
+        total_collateral_value_q64: total_collateral_value.to_bits(),
+        total_weighted_collateral_q64: total_weighted_collateral.to_bits(),
+        total_borrow_limit_q64: total_borrow_limit.to_bits(),
+        total_debt_value_q64: total_debt_value.to_bits(),
+        total_weighted_debt_q64: total_weighted_debt.to_bits(),
+        collaterals,
+        debts,
+    })
 }
 
-/* Multiplies two Q64.64 numbers and divides by a third Q64.64 number. */
-#[inline(never)]
-fn mul_div_q64(a: u128, b: u128, denom: u128) -> Result<u128> {
-    require!(denom != 0, HfError::MathOverflow);
-    let a = U256::from(a);
-    let b = U256::from(b);
-    let denom = U256::from(denom);
-    let res = a.checked_mul(b).ok_or(HfError::MathOverflow)? / denom;
-
-    Ok(res.as_u128())
+/* Per-asset entry with its share still unset; see `set_shares`. */
+fn asset_breakdown(amount: Q64, value: Q64, weighted_value: Q64) -> AssetBreakdown {
+    AssetBreakdown {
+        amount_q64: amount.to_bits(),
+        value_q64: value.to_bits(),
+        weighted_value_q64: weighted_value.to_bits(),
+        share_q64: 0,
+    }
 }
 
-/* Multiplies two Q64.64 numbers. */
-#[inline(never)]
-fn q64_mul(a_q64: u128, b_q64: u128) -> Result<u128> {
-    let a = U256::from(a_q64);
-    let b = U256::from(b_q64);
-    let prod = a.checked_mul(b).ok_or(HfError::MathOverflow)?;
-
-    Ok((prod >> 64).as_u128())
+/* Sets each entry's share of `total` (rounded down); shares stay zero when the total is zero. */
+fn set_shares(assets: &mut [AssetBreakdown], total: Q64) {
+    if total == Q64::ZERO {
+        return;
+    }
+    for asset in assets.iter_mut() {
+        // weighted_value <= total, so the ratio fits in Q64.64
+        asset.share_q64 = Q64::from_bits(asset.weighted_value_q64)
+            .saturating_div(total, Rounding::Floor)
+            .to_bits();
+    }
 }
 
-/* Divides two Q64.64 numbers. */
-#[inline(never)]
-fn q64_div(a_q64: u128, b_q64: u128) -> Result<u128> {
-    require!(b_q64 != 0, HfError::MathOverflow);
-    let a = U256::from(a_q64);
-    let b = U256::from(b_q64);
-
-    Ok(((a << 64) / b).as_u128())
-}
+// --------------- Math Helpers ---------------
 
-/* Converts a price from e8 format to Q64.64 fixed-point precision. */
+/* Converts a price from e8 format (price * 1e8) to Q64.64 fixed-point precision. */
 #[inline(always)]
-fn q64_from_price_e8(price_e8: i64) -> Result<u128> {
-    let price = U256::from(price_e8 as u128);
-    let one_q64 = U256::from(ONE_Q64_64);
-    let result = (price * one_q64) / U256::from(100_000);
-
-    Ok(result.as_u128())
+fn q64_from_price_e8(price_e8: i64, rounding: Rounding) -> Result<u128> {
+    require!(price_e8 > 0, HfError::InvalidPrice);
+    Ok(Q64::from_ratio(price_e8 as u128, PRICE_E8_SCALE, rounding).map_err(HfError::from)?.to_bits())
 }
 
 // --------------- Errors ---------------
@@ -228,15 +1320,559 @@ pub enum HfError {
     #[msg("Invalid liquidation threshold")]
     InvalidLiqThreshold,
     #[msg("Invalid borrow factor")]
-    InvalidBorrowFactor
+    InvalidBorrowFactor,
+    #[msg("Account is not owned by the Kamino lending program")]
+    InvalidAccountOwner,
+    #[msg("Account discriminator does not match the expected Kamino account")]
+    InvalidAccountDiscriminator,
+    #[msg("Obligation is not owned by the signer")]
+    ObligationOwnerMismatch,
+    #[msg("Reserve accounts do not match the obligation")]
+    ReserveAccountsMismatch,
+    #[msg("Reserve belongs to a different lending market")]
+    LendingMarketMismatch,
+    #[msg("Reserve price is stale")]
+    StalePrice,
+    #[msg("Invalid oracle configuration")]
+    InvalidOracleConfig,
+    #[msg("Oracle price is stale")]
+    StaleOraclePrice,
+    #[msg("Oracle confidence interval is too wide")]
+    OracleConfidenceTooWide,
+    #[msg("Price account does not match the reserve's configured feed")]
+    WrongPriceFeed,
+    #[msg("Exactly one price source is required per obligation asset")]
+    PriceSourcesMismatch,
+    #[msg("Too many assets to fit the HF breakdown in return data")]
+    ReturnDataTooLarge,
+    #[msg("Missing or malformed HF return data")]
+    InvalidReturnData,
+    #[msg("Health factor is below the required minimum")]
+    Unhealthy,
+    #[msg("Obligation is healthy and cannot be liquidated")]
+    ObligationHealthy,
+    #[msg("Repay or withdraw reserve is not part of the obligation")]
+    LiquidationReserveMismatch,
+    #[msg("Account is not a legacy per-user HF state of the signer")]
+    InvalidLegacyHfState,
+    #[msg("HF account does not belong to the signer")]
+    HfStateOwnerMismatch,
+    #[msg("HF state account has an unknown layout")]
+    InvalidHfStateVersion,
+    #[msg("Signer is not allowed to manage the config")]
+    Unauthorized,
+    #[msg("Invalid config parameters")]
+    InvalidConfig,
+    #[msg("Lending market is not allowed by the config")]
+    MarketNotAllowed,
+    #[msg("Obligation's elevation group is not configured on its lending market")]
+    InvalidElevationGroup,
+    #[msg("Reserve is not allowed by the config")]
+    ReserveNotAllowed,
+    #[msg("Program is paused")]
+    Paused,
+    #[msg("Max LTV must not exceed the liquidation threshold")]
+    InvalidMaxLtv,
+    #[msg("Obligation cumulative borrow rate is zero or above the reserve's")]
+    InvalidCumulativeBorrowRate,
+    #[msg("Invalid reserve borrow rate curve")]
+    InvalidBorrowRateCurve
+}
+
+/* Every `hf_math` failure surfaces as `MathOverflow`, as before the helpers were extracted. */
+impl From<MathError> for HfError {
+    fn from(_: MathError) -> Self {
+        HfError::MathOverflow
+    }
 }
 
 // --------------- Events ---------------
 
-/* Event for when a user’s HF is computed. */
+/* Event for when a user’s HF is computed.
+- Totals and per-asset entries are the spot-price `HfBreakdown`, so dashboards can explain HF moves. */
 #[event]
 pub struct HealthFactorComputed {
     pub user: Pubkey,
+    pub obligation: Pubkey,
+    pub hf_q64: u128,
+    pub borrow_hf_q64: u128,
+    pub conservative_hf_q64: Option<u128>,
+    pub pricing: HfPricing,
+    pub timestamp: i64,
+    pub total_collateral_value_q64: u128,
+    pub total_weighted_collateral_q64: u128,
+    pub total_borrow_limit_q64: u128,
+    pub total_debt_value_q64: u128,
+    pub total_weighted_debt_q64: u128,
+    pub collaterals: Vec<AssetBreakdown>,
+    pub debts: Vec<AssetBreakdown>,
+}
+
+/* Event for when `liquidate_if_unhealthy` liquidates an obligation. */
+#[event]
+pub struct ObligationLiquidated {
+    pub obligation: Pubkey,
+    pub liquidator: Pubkey,
     pub hf_q64: u128,
+    pub liquidity_amount: u64,
+    pub timestamp: i64,
+}
+
+/* Event for when the admin pauses or unpauses the program with `set_paused`. */
+#[event]
+pub struct PauseChanged {
+    pub admin: Pubkey,
+    pub paused: bool,
     pub timestamp: i64,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::U512;
+
+    /* Collateral whose max LTV equals its liquidation threshold. */
+    fn collateral(amount: u64, decimals: u8, price_e8: i64, liq_threshold_bps: u16, borrow_factor_bps: u16) -> CollateralInput {
+        CollateralInput { amount, decimals, price_e8, max_ltv_bps: liq_threshold_bps, liq_threshold_bps, borrow_factor_bps }
+    }
+
+    fn debt(amount: u64, decimals: u8, price_e8: i64) -> DebtInput {
+        DebtInput { amount, decimals, price_e8, borrow_factor_bps: 0 }
+    }
+
+    fn hf(args: &ComputeArgs) -> u128 {
+        compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap().hf_q64
+    }
+
+    /* Exact HF as a fraction (numerator, denominator), every term scaled by 10^30 * Π collateral borrow_factor. */
+    fn exact_hf(args: &ComputeArgs) -> (U512, U512) {
+        let bf = |c: &CollateralInput| U512::from(if c.borrow_factor_bps == 0 { 10_000 } else { c.borrow_factor_bps });
+        let debt_bf = |d: &DebtInput| U512::from(if d.borrow_factor_bps == 0 { 10_000 } else { d.borrow_factor_bps });
+        let bf_product = args.collaterals.iter().fold(U512::one(), |acc, c| acc * bf(c));
+        let scale = |decimals: u8| U512::from(10u64).pow(U512::from(18 - decimals));
+
+        let mut num = U512::zero();
+        for c in args.collaterals.iter() {
+            num += U512::from(c.amount) * U512::from(c.price_e8) * U512::from(c.liq_threshold_bps) * scale(c.decimals)
+                * U512::from(10_000u64) * bf_product / bf(c);
+        }
+        let mut den = U512::zero();
+        for d in args.debts.iter() {
+            den += U512::from(d.amount) * U512::from(d.price_e8) * scale(d.decimals) * debt_bf(d) * bf_product;
+        }
+        (num, den)
+    }
+
+    fn assert_not_above_exact(args: &ComputeArgs) {
+        let hf_q64 = hf(args);
+        let (num, den) = exact_hf(args);
+        assert!(U512::from(hf_q64) * den <= num << 64, "HF {hf_q64} exceeds exact {num}/{den} for {args:?}");
+    }
+
+    /* xorshift64*, enough to spread test vectors over the input space deterministically. */
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn range(&mut self, lo: u64, hi: u64) -> u64 {
+            lo + self.next() % (hi - lo + 1)
+        }
+
+        /* Values of every magnitude below 2^max_bits, not just large ones. */
+        fn magnitude(&mut self, max_bits: u64) -> u64 {
+            let bits = self.range(1, max_bits);
+            self.range(1, (1u64 << bits) - 1)
+        }
+
+        fn args(&mut self) -> ComputeArgs {
+            let collaterals = (0..self.range(1, 3))
+                .map(|_| {
+                    let borrow_factor_bps = if self.next() % 2 == 0 { 0 } else { self.range(1_000, 10_000) as u16 };
+                    let liq_threshold_bps = self.range(0, 10_000) as u16;
+                    CollateralInput {
+                        max_ltv_bps: self.range(0, liq_threshold_bps as u64) as u16,
+                        ..collateral(
+                            self.magnitude(48),
+                            self.range(0, 18) as u8,
+                            self.magnitude(40) as i64,
+                            liq_threshold_bps,
+                            borrow_factor_bps,
+                        )
+                    }
+                })
+                .collect();
+            let debts = (0..self.range(1, 3))
+                .map(|_| DebtInput {
+                    borrow_factor_bps: if self.next() % 2 == 0 { 0 } else { self.range(10_000, 50_000) as u32 },
+                    ..debt(self.magnitude(48), self.range(0, 18) as u8, self.magnitude(40) as i64)
+                })
+                .collect();
+            ComputeArgs { collaterals, debts }
+        }
+    }
+
+    #[test]
+    fn q64_from_price_e8_vectors() {
+        assert_eq!(q64_from_price_e8(100_000_000, Rounding::Floor).unwrap(), Q64::ONE.to_bits());
+        assert_eq!(q64_from_price_e8(100_000_000, Rounding::Ceil).unwrap(), Q64::ONE.to_bits());
+        assert_eq!(q64_from_price_e8(1, Rounding::Floor).unwrap(), 184_467_440_737);
+        assert_eq!(q64_from_price_e8(1, Rounding::Ceil).unwrap(), 184_467_440_738);
+        // $150.12345678
+        assert_eq!(q64_from_price_e8(15_012_345_678, Rounding::Floor).unwrap(), 2_769_288_986_681_257_006_297);
+        assert_eq!(q64_from_price_e8(i64::MAX, Rounding::Floor).unwrap(), 1_701_411_834_604_692_317_132_405_596_421);
+    }
+
+    #[test]
+    fn q64_from_price_e8_rejects_non_positive_prices() {
+        let invalid: Error = HfError::InvalidPrice.into();
+        assert_eq!(q64_from_price_e8(0, Rounding::Floor).unwrap_err(), invalid);
+        assert_eq!(q64_from_price_e8(-1, Rounding::Ceil).unwrap_err(), invalid);
+        assert_eq!(q64_from_price_e8(i64::MIN, Rounding::Floor).unwrap_err(), invalid);
+    }
+
+    #[test]
+    fn hf_never_exceeds_exact_result() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2_000 {
+            assert_not_above_exact(&rng.args());
+        }
+    }
+
+    #[test]
+    fn dust_debt_is_not_rounded_away() {
+        // 1e-18 tokens at $0.00000001: flooring would value this debt at zero and report an infinite HF.
+        let args = ComputeArgs {
+            collaterals: vec![collateral(1, 6, 1, 8_000, 0)],
+            debts: vec![debt(1, 18, 1)],
+        };
+        assert_ne!(hf(&args), u128::MAX);
+        assert_not_above_exact(&args);
+    }
+
+    /* Replaces every conservative price with the bound of a random confidence interval and EMA around spot. */
+    fn with_price_bounds(inputs: &mut HfInputs, rng: &mut Rng) {
+        let mut bounds = |price_q64: u128| {
+            let conf_q64 = price_q64 >> rng.range(1, 64);
+            let ema_q64 = match rng.next() % 3 {
+                0 => None,
+                1 => Some(price_q64 - (price_q64 >> rng.range(1, 64))),
+                _ => Some(price_q64 + (price_q64 >> rng.range(1, 64))),
+            };
+            OraclePrice { price_q64, conf_q64, ema_q64 }
+        };
+        for c in inputs.collaterals.iter_mut() {
+            c.conservative_price_q64 = bounds(c.price_q64).lower_bound_q64();
+        }
+        for d in inputs.debts.iter_mut() {
+            d.conservative_price_q64 = bounds(d.price_q64).upper_bound_q64().unwrap();
+        }
+    }
+
+    #[test]
+    fn conservative_hf_never_exceeds_spot_hf() {
+        let mut rng = Rng(0x0123_4567_89ab_cdef);
+        for _ in 0..1_000 {
+            let mut inputs = rng.args().to_inputs().unwrap();
+            with_price_bounds(&mut inputs, &mut rng);
+            let spot = compute_hf_internal(&inputs, PriceMode::Spot).unwrap().hf_q64;
+            let conservative = compute_hf_internal(&inputs, PriceMode::Conservative).unwrap().hf_q64;
+            assert!(conservative <= spot, "conservative HF {conservative} above spot {spot} for {inputs:?}");
+        }
+    }
+
+    #[test]
+    fn conservative_hf_vector() {
+        // 10 SOL at $150 ± $2 (EMA $149, 50% LT) against 500 USDC at $1 ± $0.01.
+        let mut inputs = ComputeArgs {
+            collaterals: vec![collateral(10_000_000_000, 9, 15_000_000_000, 5_000, 0)],
+            debts: vec![debt(500_000_000, 6, 100_000_000)],
+        }
+        .to_inputs()
+        .unwrap();
+        let q64 = |num: u128, den: u128| Q64::from_ratio(num, den, Rounding::Floor).unwrap().to_bits();
+        let sol = OraclePrice { price_q64: q64(150, 1), conf_q64: q64(2, 1), ema_q64: Some(q64(149, 1)) };
+        let usdc = OraclePrice { price_q64: q64(1, 1), conf_q64: q64(1, 100), ema_q64: None };
+        inputs.collaterals[0].conservative_price_q64 = sol.lower_bound_q64();
+        inputs.debts[0].conservative_price_q64 = usdc.upper_bound_q64().unwrap();
+
+        // spot: 750 / 500 = 1.5; conservative: 740 / 505 ≈ 1.465
+        let spot = compute_hf_internal(&inputs, PriceMode::Spot).unwrap().hf_q64;
+        let conservative = compute_hf_internal(&inputs, PriceMode::Conservative).unwrap().hf_q64;
+        assert_eq!(spot, q64(3, 2));
+        assert!(conservative < spot);
+        assert!(conservative.abs_diff(q64(740, 505)) <= 1 << 8, "{conservative}");
+    }
+
+    #[test]
+    fn breakdown_vector() {
+        // 10 SOL at $150 (75% LT) and 1,000 USDC (50% LT, 50% borrow factor) against 1,000 USDT.
+        // Binary-exact thresholds keep every term exact, so only the shares are rounded.
+        let args = ComputeArgs {
+            collaterals: vec![
+                collateral(10_000_000_000, 9, 15_000_000_000, 7_500, 0),
+                collateral(1_000_000_000, 6, 100_000_000, 5_000, 5_000),
+            ],
+            debts: vec![debt(1_000_000_000, 6, 100_000_000)],
+        };
+        let breakdown = compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap();
+        let int = |v: u64| Q64::from_int(v).to_bits();
+        let ratio = |n: u128, d: u128| Q64::from_ratio(n, d, Rounding::Floor).unwrap().to_bits();
+
+        assert_eq!(breakdown.hf_q64, ratio(2_125, 1_000));
+        assert_eq!(breakdown.total_collateral_value_q64, int(2_500));
+        assert_eq!(breakdown.total_weighted_collateral_q64, int(2_125));
+        assert_eq!(breakdown.total_debt_value_q64, int(1_000));
+        assert_eq!(
+            breakdown.collaterals,
+            vec![
+                AssetBreakdown { amount_q64: int(10), value_q64: int(1_500), weighted_value_q64: int(1_125), share_q64: ratio(9, 17) },
+                AssetBreakdown { amount_q64: int(1_000), value_q64: int(1_000), weighted_value_q64: int(1_000), share_q64: ratio(8, 17) },
+            ]
+        );
+        assert_eq!(
+            breakdown.debts,
+            vec![AssetBreakdown { amount_q64: int(1_000), value_q64: int(1_000), weighted_value_q64: int(1_000), share_q64: Q64::ONE.to_bits() }]
+        );
+    }
+
+    /* klend's `borrow_factor_adjusted_debt_value`: Σ borrowed_amount * price / 10^decimals * borrow_factor_pct / 100. */
+    fn klend_borrow_factor_adjusted_debt_q64(debts: &[(u64, u8, i64, u64)]) -> U512 {
+        let (mut num, mut den) = (U512::zero(), U512::one());
+        for &(amount, decimals, price_e8, borrow_factor_pct) in debts {
+            let term_den = U512::from(10u64).pow(U512::from(decimals)) * U512::from(PRICE_E8_SCALE) * U512::from(100u64);
+            num = num * term_den + U512::from(amount) * U512::from(price_e8) * U512::from(borrow_factor_pct) * den;
+            den *= term_den;
+        }
+        (num << 64) / den
+    }
+
+    #[test]
+    fn debt_is_weighted_like_klend_borrow_factor_adjusted_debt_value() {
+        let vectors: [&[(u64, u8, i64, u64)]; 3] = [
+            &[(100_000_000, 6, 100_000_000, 150)],
+            &[(500_000_000, 9, 15_012_345_678, 125), (42_424_242, 6, 99_987_654, 100)],
+            &[(1, 18, 1, 300), (u64::MAX / 3, 6, 123_456_789, 175)],
+        ];
+        for debts in vectors {
+            let args = ComputeArgs {
+                collaterals: vec![collateral(1, 0, 100_000_000, 10_000, 0)],
+                debts: debts
+                    .iter()
+                    .map(|&(amount, decimals, price_e8, pct)| DebtInput {
+                        borrow_factor_bps: pct as u32 * 100,
+                        ..debt(amount, decimals, price_e8)
+                    })
+                    .collect(),
+            };
+            let breakdown = compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap();
+            let expected = klend_borrow_factor_adjusted_debt_q64(debts);
+            let weighted = U512::from(breakdown.total_weighted_debt_q64);
+            // every debt term rounds up; the price's rounding is scaled by the amount, so bound it relatively
+            assert!(weighted >= expected && weighted - expected <= (expected >> 48) + U512::from(8), "{debts:?}");
+        }
+
+        // $300 at 50% LT against 100 USDC with a 150% borrow factor is exactly at the liquidation threshold
+        let args = ComputeArgs {
+            collaterals: vec![collateral(300, 0, 100_000_000, 5_000, 0)],
+            debts: vec![DebtInput { borrow_factor_bps: 15_000, ..debt(100_000_000, 6, 100_000_000) }],
+        };
+        let breakdown = compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap();
+        assert_eq!(breakdown.hf_q64, Q64::ONE.to_bits());
+        assert_eq!(breakdown.total_debt_value_q64, Q64::from_int(100).to_bits());
+        assert_eq!(breakdown.debts[0].weighted_value_q64, Q64::from_int(150).to_bits());
+
+        let invalid: Error = HfError::InvalidBorrowFactor.into();
+        let args = ComputeArgs {
+            collaterals: vec![],
+            debts: vec![DebtInput { borrow_factor_bps: 9_999, ..debt(1, 0, 1) }],
+        };
+        assert_eq!(compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap_err(), invalid);
+    }
+
+    #[test]
+    fn borrow_hf_uses_max_ltv_and_hf_the_liquidation_threshold() {
+        // $1,000 at 50% max LTV / 75% liquidation threshold against $500 of debt:
+        // no borrow headroom left, but still far from liquidation.
+        let args = ComputeArgs {
+            collaterals: vec![CollateralInput { max_ltv_bps: 5_000, ..collateral(1_000, 0, 100_000_000, 7_500, 0) }],
+            debts: vec![debt(500, 0, 100_000_000)],
+        };
+        let breakdown = compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap();
+        assert_eq!(breakdown.borrow_hf_q64, Q64::ONE.to_bits());
+        assert_eq!(breakdown.hf_q64, Q64::from_ratio(3, 2, Rounding::Floor).unwrap().to_bits());
+        assert_eq!(breakdown.total_borrow_limit_q64, Q64::from_int(500).to_bits());
+        assert_eq!(breakdown.total_weighted_collateral_q64, Q64::from_int(750).to_bits());
+
+        let invalid: Error = HfError::InvalidMaxLtv.into();
+        let args = ComputeArgs {
+            collaterals: vec![CollateralInput { max_ltv_bps: 7_501, ..collateral(1_000, 0, 100_000_000, 7_500, 0) }],
+            debts: vec![],
+        };
+        assert_eq!(compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap_err(), invalid);
+
+        let mut rng = Rng(0x0123_4567_89ab_cdef);
+        for _ in 0..500 {
+            let breakdown = compute_hf_internal(&rng.args().to_inputs().unwrap(), PriceMode::Spot).unwrap();
+            assert!(breakdown.borrow_hf_q64 <= breakdown.hf_q64);
+        }
+    }
+
+    #[test]
+    fn breakdown_without_debt_has_infinite_hf() {
+        let args = ComputeArgs { collaterals: vec![collateral(1, 0, 100_000_000, 8_000, 0)], debts: vec![] };
+        let breakdown = compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap();
+        assert_eq!(breakdown.hf_q64, u128::MAX);
+        assert_eq!(breakdown.total_debt_value_q64, 0);
+        assert_eq!(breakdown.collaterals[0].share_q64, Q64::ONE.to_bits());
+    }
+
+    #[test]
+    fn full_obligation_breakdown_fits_in_return_data() {
+        // klend obligations hold at most 8 deposits and 5 borrows
+        let args = ComputeArgs {
+            collaterals: vec![collateral(1, 0, 100_000_000, 8_000, 0); 8],
+            debts: vec![debt(1, 0, 100_000_000); 5],
+        };
+        let mut breakdown = compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap();
+        breakdown.conservative_hf_q64 = Some(breakdown.hf_q64);
+        assert!(breakdown.try_to_vec().unwrap().len() <= MAX_RETURN_DATA);
+    }
+
+    #[test]
+    fn breakdown_decodes_from_trimmed_return_data() {
+        let args = ComputeArgs {
+            collaterals: vec![collateral(2_000_000, 6, 100_000_000, 8_000, 0)],
+            debts: vec![debt(1_000_000, 6, 100_000_000)],
+        };
+        let mut breakdown = compute_hf_internal(&args.to_inputs().unwrap(), PriceMode::Spot).unwrap();
+        assert_eq!(breakdown.conservative_hf_q64, None);
+        let data = breakdown.try_to_vec().unwrap();
+        let end = data.iter().rposition(|&b| b != 0).unwrap() + 1;
+        assert!(end < data.len(), "vector should end in zero bytes");
+
+        assert_eq!(HfBreakdown::from_return_data(&data).unwrap(), breakdown);
+        assert_eq!(HfBreakdown::from_return_data(&data[..end]).unwrap(), breakdown);
+        breakdown.conservative_hf_q64 = Some(breakdown.hf_q64 / 2);
+        assert_eq!(HfBreakdown::from_return_data(&breakdown.try_to_vec().unwrap()).unwrap(), breakdown);
+
+        let invalid: Error = HfError::InvalidReturnData.into();
+        // both HFs, no conservative HF and five totals, then a collateral count far beyond the available bytes
+        let mut truncated = vec![0u8; 7 * 16 + 1];
+        truncated.extend([0xff; 4]);
+        assert_eq!(HfBreakdown::from_return_data(&truncated).unwrap_err(), invalid);
+        assert_eq!(HfBreakdown::from_return_data(&[0; MAX_RETURN_DATA + 1]).unwrap_err(), invalid);
+    }
+
+    #[test]
+    fn require_min_hf_boundaries() {
+        let one = Q64::ONE.to_bits();
+        let unhealthy: Error = HfError::Unhealthy.into();
+        assert!(require_min_hf(one, one).is_ok());
+        assert!(require_min_hf(u128::MAX, one).is_ok());
+        assert!(require_min_hf(0, 0).is_ok());
+        assert_eq!(require_min_hf(one - 1, one).unwrap_err(), unhealthy);
+        assert_eq!(require_min_hf(0, 1).unwrap_err(), unhealthy);
+    }
+
+    /* The `HfState` account as originally shipped, before it was keyed by obligation. */
+    #[derive(AnchorSerialize)]
+    struct BaselineHfState {
+        last_hf_q64: u128,
+        user: Pubkey,
+        last_update_slot: u64,
+    }
+
+    #[test]
+    fn legacy_hf_state_decodes_only_the_legacy_layout() {
+        let user = Pubkey::new_unique();
+        let mut data = HfState::DISCRIMINATOR.to_vec();
+        BaselineHfState { last_hf_q64: 7, user, last_update_slot: 42 }.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + 56);
+
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &crate::ID, false, 0);
+        let legacy = LegacyHfState::load(&info).unwrap();
+        assert_eq!((legacy.last_hf_q64, legacy.user, legacy.last_update_slot), (7, user, 42));
+
+        let invalid: Error = HfError::InvalidLegacyHfState.into();
+        let mut current = data.clone();
+        current.resize(HfState::SPACE, 0);
+        let err = LegacyHfState::load(&AccountInfo::new(&key, false, true, &mut lamports, &mut current, &crate::ID, false, 0));
+        assert_eq!(err.unwrap_err(), invalid);
+        let other_owner = Pubkey::new_unique();
+        let err = LegacyHfState::load(&AccountInfo::new(&key, false, true, &mut lamports, &mut data, &other_owner, false, 0));
+        assert_eq!(err.unwrap_err(), invalid);
+    }
+
+    #[test]
+    fn v1_hf_state_deserializes_after_upgrade() {
+        let (user, market, obligation) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut v1 = HfState::DISCRIMINATOR.to_vec();
+        v1.extend(7u128.to_le_bytes());
+        v1.extend(user.to_bytes());
+        v1.extend(42u64.to_le_bytes());
+        v1.extend(5u128.to_le_bytes());
+        v1.extend(market.to_bytes());
+        v1.extend(obligation.to_bytes());
+        assert_eq!(v1.len(), 8 + 136);
+
+        let mut data = v1.clone();
+        data.resize(HfState::SPACE, 0);
+        HfStateV1::decode(&v1).unwrap().upgrade().try_serialize(&mut &mut data[..]).unwrap();
+        let state = HfState::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(state.version, HF_STATE_VERSION);
+        assert_eq!(
+            (state.last_hf_q64, state.last_conservative_hf_q64, state.user, state.last_update_slot),
+            (7, 5, user, 42)
+        );
+        assert_eq!((state.market, state.obligation), (market, obligation));
+        assert_eq!((state.pricing, state.reserved), (HfPricing::Unknown, [0; 127]));
+        // `pricing` is carved out of `reserved`, so the account size is unchanged
+        assert_eq!(HfState::SPACE, 8 + 136 + 1 + 128);
+        // the original `{last_hf_q64, user, last_update_slot}` fields keep their offsets
+        assert_eq!(data[..8 + 56], v1[..8 + 56]);
+
+        // a v1 account cannot be read as the current layout, nor a current one as v1
+        assert!(HfState::try_deserialize(&mut &v1[..]).is_err());
+        let invalid: Error = HfStateV1::decode(&data).unwrap_err();
+        assert_eq!(invalid, HfError::InvalidHfStateVersion.into());
+    }
+
+    #[test]
+    fn hf_is_monotonic() {
+        let mut rng = Rng(0xdead_beef_cafe_f00d);
+        for _ in 0..500 {
+            let base = rng.args();
+            let base_hf = hf(&base);
+            let delta = rng.magnitude(16);
+
+            let mut more_collateral = base.clone();
+            more_collateral.collaterals[0].amount += delta;
+            assert!(hf(&more_collateral) >= base_hf);
+
+            let mut higher_collateral_price = base.clone();
+            higher_collateral_price.collaterals[0].price_e8 += delta as i64;
+            assert!(hf(&higher_collateral_price) >= base_hf);
+
+            let mut higher_threshold = base.clone();
+            let lt = &mut higher_threshold.collaterals[0].liq_threshold_bps;
+            *lt = (*lt).saturating_add(delta as u16).min(10_000);
+            assert!(hf(&higher_threshold) >= base_hf);
+
+            let mut more_debt = base.clone();
+            more_debt.debts[0].amount += delta;
+            assert!(hf(&more_debt) <= base_hf);
+
+            let mut higher_debt_price = base.clone();
+            higher_debt_price.debts[0].price_e8 += delta as i64;
+            assert!(hf(&higher_debt_price) <= base_hf);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use anchor_lang::prelude::*;
+use hf_math::{ten_pow, Q64, Rounding};
+
+use crate::klend::{ElevationGroup, LendingMarket, Reserve};
+use crate::{compute_hf_internal, HfError, HfInputs, PriceMode};
+
+/* klend's liquidation settings for one lending market and withdraw (collateral) reserve.
+- For obligations in an elevation group the max bonus is the lower of the reserve's and the group's. */
+#[derive(Clone, Copy, Debug)]
+pub struct LiquidationParams {
+    pub close_factor_pct: u8,
+    /// LTV (percent) above which the whole debt may be repaid; 0 disables the rule.
+    pub insolvency_risk_ltv_pct: u8,
+    /// Obligations with less debt than this (USD) may be liquidated in full.
+    pub min_full_liquidation_value: u64,
+    /// Maximum debt value (USD) repaid by a single liquidation; 0 means no cap.
+    pub max_liquidatable_value_at_once: u64,
+    pub min_bonus_bps: u16,
+    pub max_bonus_bps: u16,
+    pub bad_debt_bonus_bps: u16,
+}
+
+impl LiquidationParams {
+    pub fn new(market: &LendingMarket, withdraw_reserve: &Reserve, elevation_group: Option<&ElevationGroup>) -> Self {
+        let max_bonus_bps = elevation_group.map_or(withdraw_reserve.max_liquidation_bonus_bps, |g| {
+            withdraw_reserve.max_liquidation_bonus_bps.min(g.max_liquidation_bonus_bps)
+        });
+        Self {
+            close_factor_pct: market.liquidation_max_debt_close_factor_pct,
+            insolvency_risk_ltv_pct: market.insolvency_risk_unhealthy_ltv_pct,
+            min_full_liquidation_value: market.min_full_liquidation_value_threshold,
+            max_liquidatable_value_at_once: market.max_liquidatable_debt_market_value_at_once,
+            // the group's cap also bounds the reserve's min bonus
+            min_bonus_bps: withdraw_reserve.min_liquidation_bonus_bps.min(max_bonus_bps),
+            max_bonus_bps,
+            bad_debt_bonus_bps: withdraw_reserve.bad_debt_liquidation_bonus_bps,
+        }
+    }
+}
+
+/* What a liquidator can repay and seize in one liquidation, and where it leaves the obligation.
+- Ratios, USD values and HFs are Q64.64; token amounts are in the mints' base units, ready to pass
+  to `liquidate_if_unhealthy` (apply your own slippage to `collateral_seized_amount`).
+- A healthy obligation (HF >= 1.0) quotes zero repay and seizure. */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LiquidationQuote {
+    pub hf_q64: u128,
+    pub ltv_q64: u128,
+    pub close_factor_q64: u128,
+    pub liquidation_bonus_q64: u128,
+    pub max_repay_amount: u64,
+    pub max_repay_value_q64: u128,
+    pub collateral_seized_amount: u64,
+    pub collateral_seized_value_q64: u128,
+    pub post_liquidation_hf_q64: u128,
+}
+
+/* Quotes a liquidation repaying `inputs.debts[repay_index]` and seizing `inputs.collaterals[withdraw_index]`.
+- Close factor: `close_factor_pct` of the repaid borrow, or all of it when total debt is below
+  `min_full_liquidation_value` or LTV reaches `insolvency_risk_ltv_pct`; capped by `max_liquidatable_value_at_once`.
+- Bonus: LTV minus the liquidation LTV, clamped to the [min, max] bonus of `params` and then to 100% minus LTV;
+  the bad-debt bonus once LTV >= 100%.
+- Seized collateral = repaid value * (1 + bonus), limited to the deposit (which then limits the repay).
+- Every term rounds down so the quote never promises more than klend will allow. */
+pub fn quote_liquidation(
+    inputs: &HfInputs,
+    repay_index: usize,
+    withdraw_index: usize,
+    params: &LiquidationParams,
+) -> Result<LiquidationQuote> {
+    require!(
+        repay_index < inputs.debts.len() && withdraw_index < inputs.collaterals.len(),
+        HfError::LiquidationReserveMismatch
+    );
+    let breakdown = compute_hf_internal(inputs, PriceMode::Spot)?;
+    let total_collateral = Q64::from_bits(breakdown.total_collateral_value_q64);
+    let total_weighted = Q64::from_bits(breakdown.total_weighted_collateral_q64);
+    let total_debt = Q64::from_bits(breakdown.total_debt_value_q64);
+    // klend's LTV uses the borrow factor adjusted debt
+    let total_weighted_debt = Q64::from_bits(breakdown.total_weighted_debt_q64);
+
+    // LTV rounds down and the liquidation LTV up, so the bonus is never over-stated
+    let (ltv, liquidation_ltv) = if total_collateral == Q64::ZERO {
+        (Q64::MAX, Q64::ZERO)
+    } else {
+        (
+            total_weighted_debt.saturating_div(total_collateral, Rounding::Floor),
+            total_weighted.saturating_div(total_collateral, Rounding::Ceil),
+        )
+    };
+
+    if breakdown.hf_q64 >= Q64::ONE.to_bits() {
+        return Ok(LiquidationQuote {
+            hf_q64: breakdown.hf_q64,
+            ltv_q64: ltv.to_bits(),
+            close_factor_q64: 0,
+            liquidation_bonus_q64: 0,
+            max_repay_amount: 0,
+            max_repay_value_q64: 0,
+            collateral_seized_amount: 0,
+            collateral_seized_value_q64: 0,
+            post_liquidation_hf_q64: breakdown.hf_q64,
+        });
+    }
+
+    // ---------- Close factor ----------
+    let insolvent = params.insolvency_risk_ltv_pct > 0
+        && ltv >= Q64::from_ratio(params.insolvency_risk_ltv_pct as u128, 100, Rounding::Ceil).map_err(HfError::from)?;
+    let close_factor = if total_debt < Q64::from_int(params.min_full_liquidation_value) || insolvent {
+        Q64::ONE
+    } else {
+        Q64::from_ratio(params.close_factor_pct.min(100) as u128, 100, Rounding::Floor).map_err(HfError::from)?
+    };
+
+    // ---------- Liquidation bonus ----------
+    let bonus = if ltv >= Q64::ONE {
+        Q64::from_bps(params.bad_debt_bonus_bps, Rounding::Floor)
+    } else {
+        let max_bonus = Q64::from_bps(params.max_bonus_bps, Rounding::Floor);
+        let min_bonus = Q64::from_bps(params.min_bonus_bps, Rounding::Floor);
+        // like klend's `diff_to_bad_debt`: the seizure must not push LTV past 100%, with LTV rounded up here
+        let diff_to_bad_debt = Q64::ONE.saturating_sub(total_weighted_debt.saturating_div(total_collateral, Rounding::Ceil));
+        ltv.saturating_sub(liquidation_ltv).min(max_bonus).max(min_bonus).min(diff_to_bad_debt)
+    };
+    let seize_multiplier = Q64::ONE.checked_add(bonus).map_err(HfError::from)?;
+
+    // ---------- Repay and seized values ----------
+    let debt = &breakdown.debts[repay_index];
+    let collateral = &breakdown.collaterals[withdraw_index];
+    let collateral_value = Q64::from_bits(collateral.value_q64);
+
+    let mut repay_value = Q64::from_bits(debt.value_q64)
+        .checked_mul(close_factor, Rounding::Floor)
+        .map_err(HfError::from)?;
+    if params.max_liquidatable_value_at_once > 0 {
+        repay_value = repay_value.min(Q64::from_int(params.max_liquidatable_value_at_once));
+    }
+    let mut seized_value = repay_value.saturating_mul(seize_multiplier, Rounding::Floor);
+    if seized_value > collateral_value {
+        seized_value = collateral_value;
+        repay_value = collateral_value.checked_div(seize_multiplier, Rounding::Floor).map_err(HfError::from)?;
+    }
+
+    // ---------- Token amounts ----------
+    let repay_position = &inputs.debts[repay_index];
+    let collateral_position = &inputs.collaterals[withdraw_index];
+    let max_repay_amount = value_to_amount(
+        repay_value,
+        repay_position.price_q64,
+        repay_position.decimals,
+        Rounding::Floor,
+    )?
+    .min(repay_position.amount);
+    let collateral_seized_amount = value_to_amount(
+        seized_value,
+        collateral_position.price_q64,
+        collateral_position.decimals,
+        Rounding::Floor,
+    )?
+    .min(collateral_position.amount);
+
+    // ---------- Post-liquidation HF ----------
+    // Removes at least the quoted collateral, so the projected HF is not over-stated either
+    let seized_upper = value_to_amount(
+        seized_value,
+        collateral_position.price_q64,
+        collateral_position.decimals,
+        Rounding::Ceil,
+    )?;
+    let mut post = inputs.clone();
+    post.debts[repay_index].amount -= max_repay_amount;
+    post.collaterals[withdraw_index].amount = collateral_position.amount.saturating_sub(seized_upper);
+    let post_liquidation_hf_q64 = compute_hf_internal(&post, PriceMode::Spot)?.hf_q64;
+
+    Ok(LiquidationQuote {
+        hf_q64: breakdown.hf_q64,
+        ltv_q64: ltv.to_bits(),
+        close_factor_q64: close_factor.to_bits(),
+        liquidation_bonus_q64: bonus.to_bits(),
+        max_repay_amount,
+        max_repay_value_q64: repay_value.to_bits(),
+        collateral_seized_amount,
+        collateral_seized_value_q64: seized_value.to_bits(),
+        post_liquidation_hf_q64,
+    })
+}
+
+/* Converts a Q64.64 USD value into base units of a token with the given price and decimals. */
+fn value_to_amount(value: Q64, price_q64: u128, decimals: u8, rounding: Rounding) -> Result<u64> {
+    let tokens = value
+        .checked_div(Q64::from_bits(price_q64), rounding)
+        .map_err(HfError::from)?;
+    let scale = u64::try_from(ten_pow(decimals).map_err(HfError::from)?).map_err(|_| HfError::InvalidDecimals)?;
+    let amount = tokens
+        .checked_mul(Q64::from_int(scale), rounding)
+        .map_err(HfError::from)?
+        .to_int(rounding);
+
+    u64::try_from(amount).map_err(|_| HfError::MathOverflow.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CollateralPosition, DebtPosition};
+
+    const USD: u128 = 1u128 << 64;
+
+    fn positions(collateral: &[(u64, u16)], debt: &[u64]) -> HfInputs {
+        HfInputs {
+            collaterals: collateral
+                .iter()
+                .map(|&(amount, liq_threshold_bps)| CollateralPosition {
+                    amount,
+                    decimals: 6,
+                    price_q64: USD,
+                    conservative_price_q64: USD,
+                    max_ltv_bps: liq_threshold_bps,
+                    liq_threshold_bps,
+                    borrow_factor_bps: 0,
+                })
+                .collect(),
+            debts: debt
+                .iter()
+                .map(|&amount| DebtPosition {
+                    amount,
+                    decimals: 6,
+                    price_q64: USD,
+                    conservative_price_q64: USD,
+                    borrow_factor_bps: 0,
+                })
+                .collect(),
+        }
+    }
+
+    fn params() -> LiquidationParams {
+        LiquidationParams {
+            close_factor_pct: 50,
+            insolvency_risk_ltv_pct: 0,
+            min_full_liquidation_value: 0,
+            max_liquidatable_value_at_once: 0,
+            min_bonus_bps: 200,
+            max_bonus_bps: 1_500,
+            bad_debt_bonus_bps: 99,
+        }
+    }
+
+    fn q64(num: u128, den: u128) -> u128 {
+        Q64::from_ratio(num, den, Rounding::Floor).unwrap().to_bits()
+    }
+
+    #[test]
+    fn healthy_obligation_quotes_nothing() {
+        let inputs = positions(&[(100_000_000, 7_500)], &[50_000_000]);
+        let quote = quote_liquidation(&inputs, 0, 0, &params()).unwrap();
+        assert_eq!(quote.hf_q64, q64(3, 2));
+        assert_eq!(quote.max_repay_amount, 0);
+        assert_eq!(quote.collateral_seized_amount, 0);
+        assert_eq!(quote.post_liquidation_hf_q64, quote.hf_q64);
+    }
+
+    #[test]
+    fn close_factor_and_bonus_vector() {
+        // $100 collateral at 75% LT against $87.5 debt: LTV 87.5%, bonus 12.5%, half the debt repayable
+        let inputs = positions(&[(100_000_000, 7_500)], &[87_500_000]);
+        let quote = quote_liquidation(&inputs, 0, 0, &params()).unwrap();
+
+        assert_eq!(quote.ltv_q64, q64(7, 8));
+        assert_eq!(quote.close_factor_q64, q64(1, 2));
+        assert_eq!(quote.liquidation_bonus_q64, q64(1, 8));
+        assert_eq!(quote.max_repay_amount, 43_750_000);
+        assert_eq!(quote.max_repay_value_q64, q64(4_375, 100));
+        assert_eq!(quote.collateral_seized_amount, 49_218_750);
+        assert_eq!(quote.collateral_seized_value_q64, q64(4_921_875, 100_000));
+
+        let post = positions(&[(100_000_000 - 49_218_750, 7_500)], &[87_500_000 - 43_750_000]);
+        assert_eq!(quote.post_liquidation_hf_q64, compute_hf_internal(&post, PriceMode::Spot).unwrap().hf_q64);
+        // 0.857x -> 0.871x: the liquidation improves HF but leaves the obligation liquidatable
+        assert!(quote.post_liquidation_hf_q64 > quote.hf_q64 && quote.post_liquidation_hf_q64 < Q64::ONE.to_bits());
+    }
+
+    #[test]
+    fn elevation_group_caps_the_max_bonus() {
+        let market = LendingMarket {
+            liquidation_max_debt_close_factor_pct: 50,
+            insolvency_risk_unhealthy_ltv_pct: 0,
+            min_full_liquidation_value_threshold: 0,
+            max_liquidatable_debt_market_value_at_once: 0,
+            elevation_groups: vec![],
+        };
+        let reserve = Reserve { min_liquidation_bonus_bps: 200, max_liquidation_bonus_bps: 1_500, ..Reserve::default() };
+        let group = |max_liquidation_bonus_bps| ElevationGroup {
+            id: 1,
+            max_liquidation_bonus_bps,
+            ltv_pct: 90,
+            liquidation_threshold_pct: 92,
+            allow_new_loans: true,
+            max_reserves_as_collateral: 1,
+            debt_reserve: Pubkey::default(),
+        };
+        let bonus_range = |g: Option<&ElevationGroup>| {
+            let params = LiquidationParams::new(&market, &reserve, g);
+            (params.min_bonus_bps, params.max_bonus_bps)
+        };
+
+        assert_eq!(bonus_range(None), (200, 1_500));
+        assert_eq!(bonus_range(Some(&group(500))), (200, 500));
+        assert_eq!(bonus_range(Some(&group(2_000))), (200, 1_500));
+        assert_eq!(bonus_range(Some(&group(100))), (100, 100));
+
+        // LTV 87.5% at a 75% liquidation LTV would earn 12.5%, but the group caps it at 5%
+        let inputs = positions(&[(100_000_000, 7_500)], &[87_500_000]);
+        let params = LiquidationParams::new(&market, &reserve, Some(&group(500)));
+        let quote = quote_liquidation(&inputs, 0, 0, &params).unwrap();
+        assert_eq!(quote.liquidation_bonus_q64, Q64::from_bps(500, Rounding::Floor).to_bits());
+    }
+
+    #[test]
+    fn bonus_is_clamped_to_reserve_limits() {
+        // LTV 80% vs 75% liquidation LTV: 5% clamps up to the 10% minimum
+        let inputs = positions(&[(100_000_000, 7_500)], &[80_000_000]);
+        let quote = quote_liquidation(&inputs, 0, 0, &LiquidationParams { min_bonus_bps: 1_000, ..params() }).unwrap();
+        assert_eq!(quote.liquidation_bonus_q64, Q64::from_bps(1_000, Rounding::Floor).to_bits());
+
+        // LTV 80% vs 60%: 20% clamps down to the 15% maximum
+        let inputs = positions(&[(100_000_000, 6_000)], &[80_000_000]);
+        let quote = quote_liquidation(&inputs, 0, 0, &params()).unwrap();
+        assert_eq!(quote.liquidation_bonus_q64, Q64::from_bps(1_500, Rounding::Floor).to_bits());
+    }
+
+    #[test]
+    fn bonus_never_exceeds_the_distance_to_bad_debt() {
+        // LTV 97% vs 75% would earn the 5% maximum, but only 3% is left before the obligation is underwater
+        let inputs = positions(&[(100_000_000, 7_500)], &[97_000_000]);
+        let quote = quote_liquidation(&inputs, 0, 0, &LiquidationParams { max_bonus_bps: 500, ..params() }).unwrap();
+        assert_eq!(quote.liquidation_bonus_q64, q64(3, 100));
+        assert_eq!(quote.max_repay_amount, 48_500_000);
+        // 48.5 * 1.03, one base unit short from rounding down
+        assert_eq!(quote.collateral_seized_amount, 49_954_999);
+
+        // the min bonus does not override the clamp either
+        let params = LiquidationParams { min_bonus_bps: 500, max_bonus_bps: 500, ..params() };
+        assert_eq!(quote_liquidation(&inputs, 0, 0, &params).unwrap().liquidation_bonus_q64, q64(3, 100));
+    }
+
+    #[test]
+    fn small_or_insolvent_obligations_are_fully_liquidatable() {
+        let inputs = positions(&[(100_000_000, 7_500)], &[87_500_000]);
+        let small = quote_liquidation(&inputs, 0, 0, &LiquidationParams { min_full_liquidation_value: 100, ..params() }).unwrap();
+        assert_eq!(small.close_factor_q64, Q64::ONE.to_bits());
+
+        let insolvent = quote_liquidation(&inputs, 0, 0, &LiquidationParams { insolvency_risk_ltv_pct: 85, ..params() }).unwrap();
+        assert_eq!(insolvent.close_factor_q64, Q64::ONE.to_bits());
+
+        let solvent = quote_liquidation(&inputs, 0, 0, &LiquidationParams { insolvency_risk_ltv_pct: 90, ..params() }).unwrap();
+        assert_eq!(solvent.close_factor_q64, q64(1, 2));
+    }
+
+    #[test]
+    fn repay_is_capped_per_liquidation() {
+        let inputs = positions(&[(100_000_000, 7_500)], &[87_500_000]);
+        let quote = quote_liquidation(&inputs, 0, 0, &LiquidationParams { max_liquidatable_value_at_once: 10, ..params() }).unwrap();
+        assert_eq!(quote.max_repay_amount, 10_000_000);
+        assert_eq!(quote.collateral_seized_amount, 11_250_000);
+    }
+
+    #[test]
+    fn bad_debt_seizes_at_most_the_deposit() {
+        // $100 collateral against $120 debt: bad-debt bonus, all collateral seized, repay shrinks to match
+        let inputs = positions(&[(100_000_000, 7_500)], &[120_000_000]);
+        let params = LiquidationParams { close_factor_pct: 100, bad_debt_bonus_bps: 0, ..params() };
+        let quote = quote_liquidation(&inputs, 0, 0, &params).unwrap();
+
+        assert_eq!(quote.liquidation_bonus_q64, 0);
+        assert_eq!(quote.collateral_seized_amount, 100_000_000);
+        assert_eq!(quote.max_repay_amount, 100_000_000);
+        assert_eq!(quote.post_liquidation_hf_q64, 0);
+    }
+
+    #[test]
+    fn rejects_unknown_positions() {
+        let inputs = positions(&[(100_000_000, 7_500)], &[87_500_000]);
+        let mismatch: Error = HfError::LiquidationReserveMismatch.into();
+        assert_eq!(quote_liquidation(&inputs, 1, 0, &params()).unwrap_err(), mismatch);
+        assert_eq!(quote_liquidation(&inputs, 0, 1, &params()).unwrap_err(), mismatch);
+    }
+}
@@ -0,0 +1,143 @@
+use anchor_lang::prelude::*;
+use hf_math::Rounding;
+
+use crate::klend::Reserve;
+use crate::HfError;
+
+pub mod pyth;
+pub mod scope;
+pub mod switchboard;
+
+pub use pyth::Pyth;
+pub use scope::Scope;
+pub use switchboard::SwitchboardOnDemand;
+
+/* Freshness and confidence limits applied to every oracle price. */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct OracleConfig {
+    /// Maximum age of a price, in seconds, relative to `Clock::unix_timestamp`.
+    pub max_age_secs: u64,
+    /// Maximum confidence / price ratio, in basis points.
+    pub max_conf_bps: u16,
+}
+
+impl OracleConfig {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.max_age_secs > 0 && self.max_conf_bps > 0 && self.max_conf_bps <= 10_000,
+            HfError::InvalidOracleConfig
+        );
+        Ok(())
+    }
+}
+
+/* An oracle price in Q64.64 together with its uncertainty. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub price_q64: u128,
+    /// Confidence interval (0 when the oracle does not publish one).
+    pub conf_q64: u128,
+    /// EMA / TWAP price, when the oracle publishes one.
+    pub ema_q64: Option<u128>,
+}
+
+impl OraclePrice {
+    /* A price without confidence interval or EMA. */
+    pub fn spot(price_q64: u128) -> Self {
+        Self { price_q64, conf_q64: 0, ema_q64: None }
+    }
+
+    /* Pessimistic collateral price: min(spot, EMA, spot − conf). */
+    pub fn lower_bound_q64(&self) -> u128 {
+        let lower = self.price_q64.saturating_sub(self.conf_q64);
+        self.ema_q64.map_or(lower, |ema| lower.min(ema))
+    }
+
+    /* Pessimistic debt price: max(spot, EMA, spot + conf). */
+    pub fn upper_bound_q64(&self) -> Result<u128> {
+        let upper = self.price_q64.checked_add(self.conf_q64).ok_or(HfError::MathOverflow)?;
+        Ok(self.ema_q64.map_or(upper, |ema| upper.max(ema)))
+    }
+}
+
+/* A price oracle that can value a Kamino reserve's liquidity token. */
+pub trait PriceSource {
+    /* Loads the price from `info`, which must be the feed configured on `reserve`,
+    enforces `config` and returns it in Q64.64.
+    - The price and EMA are rounded with `rounding` (`Floor` for collateral, `Ceil` for debt);
+      the confidence interval is always rounded up so both bounds only widen. */
+    fn price(
+        &self,
+        reserve: &Reserve,
+        info: &AccountInfo,
+        config: &OracleConfig,
+        now: i64,
+        rounding: Rounding,
+    ) -> Result<OraclePrice>;
+}
+
+/* Oracle selected by the caller for a single asset. */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriceSourceKind {
+    Scope,
+    SwitchboardOnDemand,
+    Pyth,
+}
+
+impl PriceSourceKind {
+    pub fn source(self) -> &'static dyn PriceSource {
+        match self {
+            PriceSourceKind::Scope => &Scope,
+            PriceSourceKind::SwitchboardOnDemand => &SwitchboardOnDemand,
+            PriceSourceKind::Pyth => &Pyth,
+        }
+    }
+}
+
+/* Checks the age of a price published at `publish_time` against `config`. */
+fn check_age(publish_time: i64, config: &OracleConfig, now: i64) -> Result<()> {
+    require!(
+        now.saturating_sub(publish_time) <= config.max_age_secs as i64,
+        HfError::StaleOraclePrice
+    );
+    Ok(())
+}
+
+/* Checks the confidence / price ratio against `config`. */
+fn check_confidence(conf: u128, price: u128, config: &OracleConfig) -> Result<()> {
+    require!(
+        conf.saturating_mul(10_000) <= (config.max_conf_bps as u128).saturating_mul(price),
+        HfError::OracleConfidenceTooWide
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_bracket_the_spot_price() {
+        let spot = OraclePrice::spot(150);
+        assert_eq!((spot.lower_bound_q64(), spot.upper_bound_q64().unwrap()), (150, 150));
+
+        // EMA inside the confidence interval
+        let p = OraclePrice { price_q64: 150, conf_q64: 2, ema_q64: Some(149) };
+        assert_eq!((p.lower_bound_q64(), p.upper_bound_q64().unwrap()), (148, 152));
+        // EMA outside it on either side
+        let p = OraclePrice { price_q64: 150, conf_q64: 2, ema_q64: Some(140) };
+        assert_eq!((p.lower_bound_q64(), p.upper_bound_q64().unwrap()), (140, 152));
+        let p = OraclePrice { price_q64: 150, conf_q64: 2, ema_q64: Some(160) };
+        assert_eq!((p.lower_bound_q64(), p.upper_bound_q64().unwrap()), (148, 160));
+    }
+
+    #[test]
+    fn bounds_saturate_low_and_fail_high() {
+        let p = OraclePrice { price_q64: 1, conf_q64: 2, ema_q64: None };
+        assert_eq!(p.lower_bound_q64(), 0);
+
+        let overflow: Error = HfError::MathOverflow.into();
+        let p = OraclePrice { price_q64: u128::MAX, conf_q64: 1, ema_q64: None };
+        assert_eq!(p.upper_bound_q64().unwrap_err(), overflow);
+    }
+}
@@ -0,0 +1,242 @@
+use anchor_lang::prelude::*;
+use hf_math::{Q64, Rounding};
+use pyth_sdk_solana::state::SolanaPriceAccount;
+use pyth_sdk_solana::{Price, PriceFeed, PriceIdentifier};
+
+use super::{check_age, check_confidence, OracleConfig, OraclePrice, PriceSource};
+use crate::klend::Reserve;
+use crate::HfError;
+
+/* Legacy Pyth push-oracle program (owner of `SolanaPriceAccount`s). */
+pub const PYTH_ORACLE_PROGRAM_ID: Pubkey = pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+/* Pyth Solana receiver program (owner of pull-oracle `PriceUpdateV2` accounts). */
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+// --------------- PriceUpdateV2 layout ---------------
+
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+const PRICE_UPDATE_V2_VERIFICATION_LEVEL: usize = 8 + 32;
+// Borsh enum: `Partial { num_signatures: u8 }` = 0, `Full` = 1.
+const VERIFICATION_LEVEL_FULL: u8 = 1;
+// `PriceFeedMessage` fields, relative to the end of the (1-byte) `Full` verification level.
+const MESSAGE_FEED_ID: usize = 0;
+const MESSAGE_PRICE: usize = 32;
+const MESSAGE_CONF: usize = 40;
+const MESSAGE_EXPONENT: usize = 48;
+const MESSAGE_PUBLISH_TIME: usize = 52;
+const MESSAGE_EMA_PRICE: usize = 68;
+const MESSAGE_EMA_CONF: usize = 76;
+const MESSAGE_LEN: usize = 84;
+
+/* Loads the spot and EMA prices from a Pyth price account.
+- `expected_feed` is the feed configured on the Kamino reserve; any other account is rejected.
+- Accepts both pull-oracle `PriceUpdateV2` (fully verified only) and legacy push accounts. */
+pub fn load_price_feed(info: &AccountInfo, expected_feed: &Pubkey) -> Result<PriceFeed> {
+    require!(
+        *expected_feed != Pubkey::default() && info.key() == *expected_feed,
+        HfError::WrongPriceFeed
+    );
+
+    if *info.owner == PYTH_RECEIVER_PROGRAM_ID {
+        load_price_update_v2(info)
+    } else if *info.owner == PYTH_ORACLE_PROGRAM_ID {
+        SolanaPriceAccount::account_info_to_feed(info).map_err(|_| HfError::WrongPriceFeed.into())
+    } else {
+        err!(HfError::WrongPriceFeed)
+    }
+}
+
+/* Pyth feed configured in the reserve's `token_info.pyth_configuration`.
+- Confidence and EMA are both reported so callers can derive conservative bounds. */
+pub struct Pyth;
+
+impl PriceSource for Pyth {
+    fn price(
+        &self,
+        reserve: &Reserve,
+        info: &AccountInfo,
+        config: &OracleConfig,
+        now: i64,
+        rounding: Rounding,
+    ) -> Result<OraclePrice> {
+        let feed = load_price_feed(info, &reserve.pyth_price)?;
+        let price = feed.get_price_unchecked();
+        require!(price.price > 0, HfError::InvalidPrice);
+        check_age(price.publish_time, config, now)?;
+        check_confidence(price.conf as u128, price.price as u128, config)?;
+
+        let ema = feed.get_ema_price_unchecked();
+        let ema_q64 = if ema.price > 0 {
+            Some(q64_from_pyth(ema.price as u128, ema.expo, rounding)?)
+        } else {
+            None
+        };
+
+        Ok(OraclePrice {
+            price_q64: q64_from_pyth(price.price as u128, price.expo, rounding)?,
+            conf_q64: q64_from_pyth(price.conf as u128, price.expo, Rounding::Ceil)?,
+            ema_q64,
+        })
+    }
+}
+
+#[inline(always)]
+fn q64_from_pyth(mantissa: u128, expo: i32, rounding: Rounding) -> Result<u128> {
+    Ok(Q64::from_decimal(mantissa, expo, rounding).map_err(HfError::from)?.to_bits())
+}
+
+fn load_price_update_v2(info: &AccountInfo) -> Result<PriceFeed> {
+    let data = info.try_borrow_data()?;
+    require!(
+        data.len() > PRICE_UPDATE_V2_VERIFICATION_LEVEL && data[..8] == PRICE_UPDATE_V2_DISCRIMINATOR,
+        HfError::WrongPriceFeed
+    );
+    // Partially verified updates carry fewer guardian signatures than required; reject them.
+    require!(
+        data[PRICE_UPDATE_V2_VERIFICATION_LEVEL] == VERIFICATION_LEVEL_FULL,
+        HfError::WrongPriceFeed
+    );
+
+    let message = PRICE_UPDATE_V2_VERIFICATION_LEVEL + 1;
+    require!(data.len() >= message + MESSAGE_LEN, HfError::WrongPriceFeed);
+    let m = &data[message..message + MESSAGE_LEN];
+
+    let feed_id: [u8; 32] = m[MESSAGE_FEED_ID..MESSAGE_FEED_ID + 32].try_into().unwrap();
+    let expo = read_i32(m, MESSAGE_EXPONENT);
+    let publish_time = read_i64(m, MESSAGE_PUBLISH_TIME);
+    let price = Price {
+        price: read_i64(m, MESSAGE_PRICE),
+        conf: read_u64(m, MESSAGE_CONF),
+        expo,
+        publish_time,
+    };
+    let ema_price = Price {
+        price: read_i64(m, MESSAGE_EMA_PRICE),
+        conf: read_u64(m, MESSAGE_EMA_CONF),
+        expo,
+        publish_time,
+    };
+
+    Ok(PriceFeed::new(PriceIdentifier::new(feed_id), price, ema_price))
+}
+
+#[inline(always)]
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[inline(always)]
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[inline(always)]
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const CONFIG: OracleConfig = OracleConfig { max_age_secs: 60, max_conf_bps: 100 };
+
+    /* A fully verified `PriceUpdateV2` with a -8 exponent. */
+    fn price_update(price: i64, conf: u64, publish_time: i64, ema_price: i64) -> Vec<u8> {
+        let mut data = vec![0u8; PRICE_UPDATE_V2_VERIFICATION_LEVEL + 1 + MESSAGE_LEN + 8];
+        data[..8].copy_from_slice(&PRICE_UPDATE_V2_DISCRIMINATOR);
+        data[PRICE_UPDATE_V2_VERIFICATION_LEVEL] = VERIFICATION_LEVEL_FULL;
+        let m = PRICE_UPDATE_V2_VERIFICATION_LEVEL + 1;
+        data[m + MESSAGE_PRICE..][..8].copy_from_slice(&price.to_le_bytes());
+        data[m + MESSAGE_CONF..][..8].copy_from_slice(&conf.to_le_bytes());
+        data[m + MESSAGE_EXPONENT..][..4].copy_from_slice(&(-8i32).to_le_bytes());
+        data[m + MESSAGE_PUBLISH_TIME..][..8].copy_from_slice(&publish_time.to_le_bytes());
+        data[m + MESSAGE_EMA_PRICE..][..8].copy_from_slice(&ema_price.to_le_bytes());
+        data
+    }
+
+    fn price(reserve: &Reserve, key: &Pubkey, owner: &Pubkey, data: &mut [u8]) -> Result<OraclePrice> {
+        price_rounded(reserve, key, owner, data, Rounding::Floor)
+    }
+
+    fn price_rounded(
+        reserve: &Reserve,
+        key: &Pubkey,
+        owner: &Pubkey,
+        data: &mut [u8],
+        rounding: Rounding,
+    ) -> Result<OraclePrice> {
+        let mut lamports = 0;
+        let info = AccountInfo::new(key, false, false, &mut lamports, data, owner, false, 0);
+        Pyth.price(reserve, &info, &CONFIG, NOW, rounding)
+    }
+
+    fn q64(mantissa: u128) -> u128 {
+        Q64::from_decimal(mantissa, -8, Rounding::Floor).unwrap().to_bits()
+    }
+
+    #[test]
+    fn reads_price_confidence_and_ema() {
+        let feed = Pubkey::new_unique();
+        let reserve = Reserve { pyth_price: feed, ..Reserve::default() };
+        // $150.12345678 ± $1, EMA $149
+        let mut data = price_update(15_012_345_678, 100_000_000, NOW - 60, 14_900_000_000);
+        let p = price(&reserve, &feed, &PYTH_RECEIVER_PROGRAM_ID, &mut data).unwrap();
+
+        assert_eq!((p.price_q64, p.conf_q64, p.ema_q64), (q64(15_012_345_678), q64(100_000_000), Some(q64(14_900_000_000))));
+        assert_eq!(p.lower_bound_q64(), q64(14_900_000_000));
+        assert_eq!(p.upper_bound_q64().unwrap(), q64(15_012_345_678) + q64(100_000_000));
+    }
+
+    #[test]
+    fn debt_side_rounds_up() {
+        let feed = Pubkey::new_unique();
+        let reserve = Reserve { pyth_price: feed, ..Reserve::default() };
+        // $150.12345678 ± $0.00000001, EMA $149.99999999: none of them is exact in Q64.64
+        let mut data = price_update(15_012_345_678, 1, NOW, 14_999_999_999);
+        let floor = price_rounded(&reserve, &feed, &PYTH_RECEIVER_PROGRAM_ID, &mut data, Rounding::Floor).unwrap();
+        let ceil = price_rounded(&reserve, &feed, &PYTH_RECEIVER_PROGRAM_ID, &mut data, Rounding::Ceil).unwrap();
+
+        assert_eq!(ceil.price_q64, floor.price_q64 + 1);
+        assert_eq!(ceil.ema_q64.unwrap(), floor.ema_q64.unwrap() + 1);
+        // the confidence interval widens both bounds whatever the side
+        let conf = Q64::from_decimal(1, -8, Rounding::Ceil).unwrap().to_bits();
+        assert_eq!((floor.conf_q64, ceil.conf_q64), (conf, conf));
+        assert!(floor.lower_bound_q64() < ceil.upper_bound_q64().unwrap());
+    }
+
+    #[test]
+    fn rejects_stale_or_uncertain_prices() {
+        let feed = Pubkey::new_unique();
+        let reserve = Reserve { pyth_price: feed, ..Reserve::default() };
+
+        let stale: Error = HfError::StaleOraclePrice.into();
+        let mut data = price_update(15_000_000_000, 0, NOW - 61, 0);
+        assert_eq!(price(&reserve, &feed, &PYTH_RECEIVER_PROGRAM_ID, &mut data).unwrap_err(), stale);
+
+        // 1.01% of the price against a 1% cap
+        let too_wide: Error = HfError::OracleConfidenceTooWide.into();
+        let mut data = price_update(15_000_000_000, 151_500_000, NOW, 0);
+        assert_eq!(price(&reserve, &feed, &PYTH_RECEIVER_PROGRAM_ID, &mut data).unwrap_err(), too_wide);
+        let mut data = price_update(15_000_000_000, 150_000_000, NOW, 0);
+        assert!(price(&reserve, &feed, &PYTH_RECEIVER_PROGRAM_ID, &mut data).is_ok());
+    }
+
+    #[test]
+    fn rejects_accounts_other_than_the_reserve_feed() {
+        let feed = Pubkey::new_unique();
+        let reserve = Reserve { pyth_price: feed, ..Reserve::default() };
+        let wrong: Error = HfError::WrongPriceFeed.into();
+        let mut data = price_update(15_000_000_000, 0, NOW, 0);
+
+        assert_eq!(price(&reserve, &Pubkey::new_unique(), &PYTH_RECEIVER_PROGRAM_ID, &mut data).unwrap_err(), wrong);
+        assert_eq!(price(&reserve, &feed, &Pubkey::new_unique(), &mut data).unwrap_err(), wrong);
+        let unset = Reserve::default();
+        assert_eq!(price(&unset, &Pubkey::default(), &PYTH_RECEIVER_PROGRAM_ID, &mut data).unwrap_err(), wrong);
+
+        // partially verified updates
+        data[PRICE_UPDATE_V2_VERIFICATION_LEVEL] = 0;
+        assert_eq!(price(&reserve, &feed, &PYTH_RECEIVER_PROGRAM_ID, &mut data).unwrap_err(), wrong);
+    }
+}
@@ -0,0 +1,171 @@
+use anchor_lang::prelude::*;
+use hf_math::{Q64, Rounding};
+
+use super::{check_age, OracleConfig, OraclePrice, PriceSource};
+use crate::klend::Reserve;
+use crate::HfError;
+
+/* Kamino Scope oracle aggregator program. */
+pub const SCOPE_PROGRAM_ID: Pubkey = pubkey!("HFn8GnPADiny6XqUoWE8uRPPxb29ikn4yTuPa9MF2fWJ");
+
+// --------------- OraclePrices layout ---------------
+
+const ORACLE_PRICES_DISCRIMINATOR: [u8; 8] = [89, 128, 118, 221, 6, 72, 180, 146];
+const ORACLE_PRICES_PRICES: usize = 8 + 32;
+const MAX_ENTRIES: usize = 512;
+// DatedPrice { price: { value: u64, exp: u64 }, last_updated_slot: u64, unix_timestamp: u64, .. }
+const DATED_PRICE_SIZE: usize = 56;
+const DATED_PRICE_VALUE: usize = 0;
+const DATED_PRICE_EXP: usize = 8;
+const DATED_PRICE_UNIX_TIMESTAMP: usize = 24;
+// Unused `price_chain` entries are set to `u16::MAX`.
+const CHAIN_TERMINATOR: u16 = u16::MAX;
+
+/* Scope price feed configured in the reserve's `token_info.scope_configuration`.
+- Like klend, the price is the product of every entry of the reserve's `price_chain`
+  (e.g. JitoSOL/SOL * SOL/USD), and it is as old as the oldest entry.
+- Scope does not publish a confidence interval, so `max_conf_bps` is not applied and
+  the conservative bounds equal the spot price. */
+pub struct Scope;
+
+impl PriceSource for Scope {
+    fn price(
+        &self,
+        reserve: &Reserve,
+        info: &AccountInfo,
+        config: &OracleConfig,
+        now: i64,
+        rounding: Rounding,
+    ) -> Result<OraclePrice> {
+        require!(
+            reserve.scope_price_feed != Pubkey::default() && info.key() == reserve.scope_price_feed,
+            HfError::WrongPriceFeed
+        );
+        require_keys_eq!(*info.owner, SCOPE_PROGRAM_ID, HfError::WrongPriceFeed);
+        require!(reserve.scope_price_chain[0] != CHAIN_TERMINATOR, HfError::WrongPriceFeed);
+
+        let data = info.try_borrow_data()?;
+        require!(
+            data.len() >= ORACLE_PRICES_PRICES + MAX_ENTRIES * DATED_PRICE_SIZE
+                && data[..8] == ORACLE_PRICES_DISCRIMINATOR,
+            HfError::WrongPriceFeed
+        );
+
+        let mut price = Q64::ONE;
+        for &index in reserve.scope_price_chain.iter().take_while(|&&i| i != CHAIN_TERMINATOR) {
+            require!((index as usize) < MAX_ENTRIES, HfError::WrongPriceFeed);
+            let base = ORACLE_PRICES_PRICES + index as usize * DATED_PRICE_SIZE;
+            let value = read_u64(&data, base + DATED_PRICE_VALUE);
+            let exp = read_u64(&data, base + DATED_PRICE_EXP);
+            let timestamp = read_u64(&data, base + DATED_PRICE_UNIX_TIMESTAMP);
+
+            require!(value > 0, HfError::InvalidPrice);
+            require!(exp <= 38, HfError::InvalidPrice);
+            check_age(timestamp as i64, config, now)?;
+
+            let entry = Q64::from_decimal(value as u128, -(exp as i32), rounding).map_err(HfError::from)?;
+            price = price.checked_mul(entry, rounding).map_err(HfError::from)?;
+        }
+        require!(price > Q64::ZERO, HfError::InvalidPrice);
+
+        Ok(OraclePrice::spot(price.to_bits()))
+    }
+}
+
+#[inline(always)]
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const CONFIG: OracleConfig = OracleConfig { max_age_secs: 60, max_conf_bps: 100 };
+
+    fn oracle_prices(entries: &[(u16, u64, u64, i64)]) -> Vec<u8> {
+        let mut data = vec![0u8; ORACLE_PRICES_PRICES + MAX_ENTRIES * DATED_PRICE_SIZE];
+        data[..8].copy_from_slice(&ORACLE_PRICES_DISCRIMINATOR);
+        for &(index, value, exp, timestamp) in entries {
+            let base = ORACLE_PRICES_PRICES + index as usize * DATED_PRICE_SIZE;
+            data[base + DATED_PRICE_VALUE..][..8].copy_from_slice(&value.to_le_bytes());
+            data[base + DATED_PRICE_EXP..][..8].copy_from_slice(&exp.to_le_bytes());
+            data[base + DATED_PRICE_UNIX_TIMESTAMP..][..8].copy_from_slice(&(timestamp as u64).to_le_bytes());
+        }
+        data
+    }
+
+    fn reserve(feed: Pubkey, chain: [u16; 4]) -> Reserve {
+        Reserve { scope_price_feed: feed, scope_price_chain: chain, ..Reserve::default() }
+    }
+
+    fn price(reserve: &Reserve, key: &Pubkey, owner: &Pubkey, data: &mut [u8]) -> Result<OraclePrice> {
+        price_rounded(reserve, key, owner, data, Rounding::Floor)
+    }
+
+    fn price_rounded(
+        reserve: &Reserve,
+        key: &Pubkey,
+        owner: &Pubkey,
+        data: &mut [u8],
+        rounding: Rounding,
+    ) -> Result<OraclePrice> {
+        let mut lamports = 0;
+        let info = AccountInfo::new(key, false, false, &mut lamports, data, owner, false, 0);
+        Scope.price(reserve, &info, &CONFIG, NOW, rounding)
+    }
+
+    #[test]
+    fn multiplies_the_price_chain() {
+        let feed = Pubkey::new_unique();
+        // JitoSOL/SOL 1.25 * SOL/USD 150
+        let mut data = oracle_prices(&[(3, 125, 2, NOW), (7, 15_000, 2, NOW - 60)]);
+        let p = price(&reserve(feed, [3, 7, CHAIN_TERMINATOR, CHAIN_TERMINATOR]), &feed, &SCOPE_PROGRAM_ID, &mut data).unwrap();
+
+        let expected = Q64::from_ratio(375, 2, Rounding::Floor).unwrap().to_bits();
+        assert_eq!(p, OraclePrice::spot(expected));
+        assert_eq!((p.lower_bound_q64(), p.upper_bound_q64().unwrap()), (expected, expected));
+    }
+
+    #[test]
+    fn debt_side_rounds_the_chain_up() {
+        let feed = Pubkey::new_unique();
+        // 1.05 * 150.3 = 157.815, not exact in Q64.64
+        let mut data = oracle_prices(&[(3, 105, 2, NOW), (7, 1_503, 1, NOW)]);
+        let chained = reserve(feed, [3, 7, CHAIN_TERMINATOR, CHAIN_TERMINATOR]);
+        let floor = price_rounded(&chained, &feed, &SCOPE_PROGRAM_ID, &mut data, Rounding::Floor).unwrap();
+        let ceil = price_rounded(&chained, &feed, &SCOPE_PROGRAM_ID, &mut data, Rounding::Ceil).unwrap();
+
+        let exact_floor = Q64::from_ratio(157_815, 1_000, Rounding::Floor).unwrap().to_bits();
+        assert!(floor.price_q64 <= exact_floor && exact_floor < ceil.price_q64);
+        assert!(ceil.price_q64 - floor.price_q64 <= 1 << 8);
+    }
+
+    #[test]
+    fn chain_is_as_old_as_its_oldest_entry() {
+        let feed = Pubkey::new_unique();
+        let mut data = oracle_prices(&[(3, 125, 2, NOW), (7, 15_000, 2, NOW - 61)]);
+        let stale: Error = HfError::StaleOraclePrice.into();
+        let chained = reserve(feed, [3, 7, CHAIN_TERMINATOR, CHAIN_TERMINATOR]);
+        assert_eq!(price(&chained, &feed, &SCOPE_PROGRAM_ID, &mut data).unwrap_err(), stale);
+
+        let fresh_only = reserve(feed, [3, CHAIN_TERMINATOR, CHAIN_TERMINATOR, CHAIN_TERMINATOR]);
+        assert!(price(&fresh_only, &feed, &SCOPE_PROGRAM_ID, &mut data).is_ok());
+    }
+
+    #[test]
+    fn rejects_accounts_other_than_the_reserve_feed() {
+        let feed = Pubkey::new_unique();
+        let mut data = oracle_prices(&[(3, 125, 2, NOW)]);
+        let wrong: Error = HfError::WrongPriceFeed.into();
+        let single = reserve(feed, [3, CHAIN_TERMINATOR, CHAIN_TERMINATOR, CHAIN_TERMINATOR]);
+
+        assert_eq!(price(&single, &Pubkey::new_unique(), &SCOPE_PROGRAM_ID, &mut data).unwrap_err(), wrong);
+        assert_eq!(price(&single, &feed, &Pubkey::new_unique(), &mut data).unwrap_err(), wrong);
+        let no_chain = reserve(feed, [CHAIN_TERMINATOR; 4]);
+        assert_eq!(price(&no_chain, &feed, &SCOPE_PROGRAM_ID, &mut data).unwrap_err(), wrong);
+        let out_of_range = reserve(feed, [MAX_ENTRIES as u16, CHAIN_TERMINATOR, CHAIN_TERMINATOR, CHAIN_TERMINATOR]);
+        assert_eq!(price(&out_of_range, &feed, &SCOPE_PROGRAM_ID, &mut data).unwrap_err(), wrong);
+    }
+}
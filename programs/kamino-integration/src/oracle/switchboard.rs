@@ -0,0 +1,165 @@
+use anchor_lang::prelude::*;
+use hf_math::{Q64, Rounding};
+
+use super::{check_age, check_confidence, OracleConfig, OraclePrice, PriceSource};
+use crate::klend::Reserve;
+use crate::HfError;
+
+/* Switchboard On-Demand program (owner of `PullFeedAccountData` accounts). */
+pub const SWITCHBOARD_ON_DEMAND_PROGRAM_ID: Pubkey = pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv");
+
+// --------------- PullFeedAccountData layout ---------------
+
+const PULL_FEED_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+const PULL_FEED_LAST_UPDATE_TIMESTAMP: usize = 8 + 2208;
+// CurrentResult { value: i128, std_dev: i128, .. }
+const PULL_FEED_RESULT_VALUE: usize = 8 + 2256;
+const PULL_FEED_RESULT_STD_DEV: usize = 8 + 2272;
+const PULL_FEED_MIN_LEN: usize = 8 + 2384;
+// On-demand results are fixed-point decimals with 18 fractional digits.
+const PULL_FEED_DECIMALS: i32 = 18;
+
+/* Switchboard On-Demand pull feed configured in the reserve's `token_info.switchboard_configuration`.
+- The feed's standard deviation is used as its confidence interval; there is no EMA. */
+pub struct SwitchboardOnDemand;
+
+impl PriceSource for SwitchboardOnDemand {
+    fn price(
+        &self,
+        reserve: &Reserve,
+        info: &AccountInfo,
+        config: &OracleConfig,
+        now: i64,
+        rounding: Rounding,
+    ) -> Result<OraclePrice> {
+        require!(
+            reserve.switchboard_price_aggregator != Pubkey::default()
+                && info.key() == reserve.switchboard_price_aggregator,
+            HfError::WrongPriceFeed
+        );
+        require_keys_eq!(*info.owner, SWITCHBOARD_ON_DEMAND_PROGRAM_ID, HfError::WrongPriceFeed);
+
+        let data = info.try_borrow_data()?;
+        require!(
+            data.len() >= PULL_FEED_MIN_LEN && data[..8] == PULL_FEED_DISCRIMINATOR,
+            HfError::WrongPriceFeed
+        );
+
+        let value = read_i128(&data, PULL_FEED_RESULT_VALUE);
+        let std_dev = read_i128(&data, PULL_FEED_RESULT_STD_DEV);
+        let last_update = i64::from_le_bytes(
+            data[PULL_FEED_LAST_UPDATE_TIMESTAMP..PULL_FEED_LAST_UPDATE_TIMESTAMP + 8]
+                .try_into()
+                .unwrap(),
+        );
+
+        require!(value > 0, HfError::InvalidPrice);
+        check_age(last_update, config, now)?;
+        check_confidence(std_dev.unsigned_abs(), value as u128, config)?;
+
+        Ok(OraclePrice {
+            price_q64: Q64::from_decimal(value as u128, -PULL_FEED_DECIMALS, rounding)
+                .map_err(HfError::from)?
+                .to_bits(),
+            conf_q64: Q64::from_decimal(std_dev.unsigned_abs(), -PULL_FEED_DECIMALS, Rounding::Ceil)
+                .map_err(HfError::from)?
+                .to_bits(),
+            ema_q64: None,
+        })
+    }
+}
+
+#[inline(always)]
+fn read_i128(data: &[u8], offset: usize) -> i128 {
+    i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const CONFIG: OracleConfig = OracleConfig { max_age_secs: 60, max_conf_bps: 100 };
+    const ONE: i128 = 1_000_000_000_000_000_000;
+
+    fn pull_feed(value: i128, std_dev: i128, last_update: i64) -> Vec<u8> {
+        let mut data = vec![0u8; PULL_FEED_MIN_LEN];
+        data[..8].copy_from_slice(&PULL_FEED_DISCRIMINATOR);
+        data[PULL_FEED_RESULT_VALUE..][..16].copy_from_slice(&value.to_le_bytes());
+        data[PULL_FEED_RESULT_STD_DEV..][..16].copy_from_slice(&std_dev.to_le_bytes());
+        data[PULL_FEED_LAST_UPDATE_TIMESTAMP..][..8].copy_from_slice(&last_update.to_le_bytes());
+        data
+    }
+
+    fn price(reserve: &Reserve, key: &Pubkey, owner: &Pubkey, data: &mut [u8]) -> Result<OraclePrice> {
+        price_rounded(reserve, key, owner, data, Rounding::Floor)
+    }
+
+    fn price_rounded(
+        reserve: &Reserve,
+        key: &Pubkey,
+        owner: &Pubkey,
+        data: &mut [u8],
+        rounding: Rounding,
+    ) -> Result<OraclePrice> {
+        let mut lamports = 0;
+        let info = AccountInfo::new(key, false, false, &mut lamports, data, owner, false, 0);
+        SwitchboardOnDemand.price(reserve, &info, &CONFIG, NOW, rounding)
+    }
+
+    #[test]
+    fn scales_18_decimal_results() {
+        let feed = Pubkey::new_unique();
+        let reserve = Reserve { switchboard_price_aggregator: feed, ..Reserve::default() };
+        // $150.5 ± $0.25
+        let mut data = pull_feed(150 * ONE + ONE / 2, ONE / 4, NOW - 60);
+        let p = price(&reserve, &feed, &SWITCHBOARD_ON_DEMAND_PROGRAM_ID, &mut data).unwrap();
+
+        let q64 = |num: u128, den: u128| Q64::from_ratio(num, den, Rounding::Floor).unwrap().to_bits();
+        assert_eq!((p.price_q64, p.conf_q64, p.ema_q64), (q64(301, 2), q64(1, 4), None));
+        assert_eq!(p.lower_bound_q64(), q64(601, 4));
+        assert_eq!(p.upper_bound_q64().unwrap(), q64(603, 4));
+    }
+
+    #[test]
+    fn debt_side_rounds_up() {
+        let feed = Pubkey::new_unique();
+        let reserve = Reserve { switchboard_price_aggregator: feed, ..Reserve::default() };
+        let owner = SWITCHBOARD_ON_DEMAND_PROGRAM_ID;
+        let mut data = pull_feed(150 * ONE + 1, 1, NOW);
+        let floor = price_rounded(&reserve, &feed, &owner, &mut data, Rounding::Floor).unwrap();
+        let ceil = price_rounded(&reserve, &feed, &owner, &mut data, Rounding::Ceil).unwrap();
+
+        assert_eq!(ceil.price_q64, floor.price_q64 + 1);
+        // a 1e-18 deviation is ~18.4 Q64.64 ulps, rounded up on both sides
+        assert_eq!((floor.conf_q64, ceil.conf_q64), (19, 19));
+    }
+
+    #[test]
+    fn rejects_stale_uncertain_or_negative_results() {
+        let feed = Pubkey::new_unique();
+        let reserve = Reserve { switchboard_price_aggregator: feed, ..Reserve::default() };
+        let owner = SWITCHBOARD_ON_DEMAND_PROGRAM_ID;
+
+        let stale: Error = HfError::StaleOraclePrice.into();
+        assert_eq!(price(&reserve, &feed, &owner, &mut pull_feed(150 * ONE, 0, NOW - 61)).unwrap_err(), stale);
+        // a standard deviation of 1.01% against a 1% cap
+        let too_wide: Error = HfError::OracleConfidenceTooWide.into();
+        assert_eq!(price(&reserve, &feed, &owner, &mut pull_feed(100 * ONE, ONE + ONE / 100, NOW)).unwrap_err(), too_wide);
+        let invalid: Error = HfError::InvalidPrice.into();
+        assert_eq!(price(&reserve, &feed, &owner, &mut pull_feed(-150 * ONE, 0, NOW)).unwrap_err(), invalid);
+    }
+
+    #[test]
+    fn rejects_accounts_other_than_the_reserve_feed() {
+        let feed = Pubkey::new_unique();
+        let reserve = Reserve { switchboard_price_aggregator: feed, ..Reserve::default() };
+        let wrong: Error = HfError::WrongPriceFeed.into();
+        let mut data = pull_feed(150 * ONE, 0, NOW);
+
+        assert_eq!(price(&reserve, &Pubkey::new_unique(), &SWITCHBOARD_ON_DEMAND_PROGRAM_ID, &mut data).unwrap_err(), wrong);
+        assert_eq!(price(&reserve, &feed, &Pubkey::new_unique(), &mut data).unwrap_err(), wrong);
+        data[0] ^= 1;
+        assert_eq!(price(&reserve, &feed, &SWITCHBOARD_ON_DEMAND_PROGRAM_ID, &mut data).unwrap_err(), wrong);
+    }
+}
@@ -6,8 +6,9 @@ import { SystemProgram, Keypair } from "@solana/web3.js";
 import { address } from '@solana/addresses';
 import type { Address } from '@solana/addresses';
 import { createKeyPairSignerFromBytes } from "@solana/kit";
-import { loadReserveData, setUpConnections, extractAssetFromObligation, fundLiquidatorWithUsdc, airdropSol, convertHfQ64ToDecimal } from './utils/kamino-utils';
-import { createRefreshInstructions, executeKaminoBorrow, executeKaminoDeposit, executeKaminoLiquidation, waitForMarketSync } from "./kamino-sdk-operations.ts/kamino_operations";
+import { createHash } from "crypto";
+import { loadReserveData, setUpConnections, extractAssetFromObligation, fundLiquidatorWithUsdc, airdropSol, convertHfQ64ToDecimal, getObligationReserveAccounts, sendAndConfirmTx, setAccountData, toKitInstruction } from './utils/kamino-utils';
+import { buildKaminoLiquidationIxs, createRefreshInstructions, executeKaminoBorrow, executeKaminoDeposit, executeKaminoLiquidation, waitForMarketSync } from "./kamino-sdk-operations.ts/kamino_operations";
 
 const MAIN_MARKET_ADDRESS: Address = address("7u3HeHxYDLhnCoErrtycNokbQYbWGzLs6JSDqGAv5PfF");
 const SOL_MINT_ADDRESS: Address = address("So11111111111111111111111111111111111111112");
@@ -24,17 +25,56 @@ describe("kamino_integration with deposit and withdraw", () => {
   const url = new URL(connection.rpcEndpoint);
   const { rpc, ws } = setUpConnections(url);
 
-  const [hfStatePda] = anchor.web3.PublicKey.findProgramAddressSync(
-    [Buffer.from("hf"), wallet.publicKey.toBuffer()],
-    program.programId
-  );
+  const lendingMarket = new anchor.web3.PublicKey(MAIN_MARKET_ADDRESS);
+  const hfStatePdaFor = (obligation: anchor.web3.PublicKey) =>
+    anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("hf"), lendingMarket.toBuffer(), obligation.toBuffer()],
+      program.programId
+    )[0];
 
   let signer: Awaited<ReturnType<typeof createKeyPairSignerFromBytes>>;
 
+  // Simulates a transaction whose last instruction returns an `HfBreakdown` and decodes it
+  const simulateBreakdown = async (tx: anchor.web3.Transaction) => {
+    tx.feePayer = wallet.publicKey;
+    const simulation = await connection.simulateTransaction(tx);
+    if (simulation.value.err) throw new Error(`simulation failed: ${JSON.stringify(simulation.value.err)}`);
+
+    // Return data in transaction metadata has its trailing zero bytes trimmed
+    const returnData = Buffer.alloc(1024);
+    Buffer.from(simulation.value.returnData!.data[0], "base64").copy(returnData);
+    return program.coder.types.decode("HfBreakdown", returnData);
+  };
+
+  const KLEND_PROGRAM_ID = new anchor.web3.PublicKey("KLend2g3cP87fffoy8q1mQqGKjrxjC8boSyAYavgmjD");
+  const configPda = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId)[0];
+  const programData = anchor.web3.PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  )[0];
+  const configParams = {
+    klendProgram: KLEND_PROGRAM_ID,
+    allowedMarkets: [lendingMarket],
+    allowedReserves: [] as anchor.web3.PublicKey[],
+    maxPriceAgeSlots: new anchor.BN(25),
+    maxOracleAgeSecs: new anchor.BN(120),
+    maxOracleConfBps: 200,
+    warningHfQ64: new anchor.BN(12).shln(64).divn(10),
+    criticalHfQ64: new anchor.BN(105).shln(64).divn(100),
+  };
+
   before(async () => {
     const solAmountToWrap = 4 // 4 SOL
     await airdropSol(connection, wallet.publicKey, solAmountToWrap);
 
+    // The provider wallet deployed the program, so it is the upgrade authority allowed to create the config.
+    if (!(await connection.getAccountInfo(configPda))) {
+      await program.methods
+        .initializeConfig(configParams)
+        .accounts({ admin: wallet.publicKey, programData })
+        .rpc();
+    }
+
     signer = await createKeyPairSignerFromBytes(wallet.payer.secretKey);
 
     try {
@@ -104,12 +144,15 @@ describe("kamino_integration with deposit and withdraw", () => {
       if (assetData) debts.push(assetData);
     }
 
+    const obligation = new anchor.web3.PublicKey(userObligation.obligationAddress);
+    const hfStatePda = hfStatePdaFor(obligation);
     await program.methods
       .computeHf({
         collaterals: collaterals.map(c => ({
           amount: c.amount,
           decimals: c.decimals,
           priceE8: c.priceE8,
+          maxLtvBps: c.maxLtvBps,
           liqThresholdBps: c.liqThresholdBps,
           borrowFactorBps: c.borrowFactorBps,
         })),
@@ -117,21 +160,25 @@ describe("kamino_integration with deposit and withdraw", () => {
           amount: d.amount,
           decimals: d.decimals,
           priceE8: d.priceE8,
+          borrowFactorBps: d.borrowFactorBps,
         })),
       })
       .accounts({
         hfState: hfStatePda,
         user: wallet.publicKey,
+        obligation,
+        lendingMarket,
         systemProgram: SystemProgram.programId,
       })
       .rpc();
 
-    // Read back the computed HF
+    // Read back the computed HF; compute_hf cannot verify its inputs, so the stored HF says so
     const hfState = await program.account.hfState.fetch(hfStatePda);
+    if (!("callerInputs" in hfState.pricing)) throw new Error(`unexpected pricing ${JSON.stringify(hfState.pricing)}`);
 
     // Convert to decimal using utility function
     const hfDecimal = convertHfQ64ToDecimal(hfState);
-    console.log(`On-chain Health Factor (Q64.64): ${hfDecimal.toFixed(4)}x`);
+    console.log(`On-chain Health Factor from caller inputs (Q64.64): ${hfDecimal.toFixed(4)}x`);
 
     if (hfDecimal < HEALTH_FACTOR_THRESHOLD) {
       const liquidatorFundAmountSOL = 1; // 1 SOL
@@ -172,4 +219,506 @@ describe("kamino_integration with deposit and withdraw", () => {
       console.log("Health Factor is greater than 1.0, no liquidation needed");
     }
   });
+
+  it("computes HF from the on-chain Kamino obligation", async () => {
+    const { market: loadedMarket } = await loadReserveData({
+      rpc,
+      marketPubkey: MAIN_MARKET_ADDRESS,
+      mintPubkey: SOL_MINT_ADDRESS,
+    });
+
+    const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+    if (!userObligation) throw new Error("User has no Kamino obligation");
+
+    // The program rejects reserve prices that were not refreshed recently
+    const reserveAccounts = getObligationReserveAccounts(userObligation);
+    const reserves = reserveAccounts.map(r => loadedMarket.getReserveByAddress(address(r.pubkey.toBase58()))!);
+    await sendAndConfirmTx({ rpc, wsRpc: ws }, signer, createRefreshInstructions(loadedMarket, reserves), [], "refresh reserves");
+
+    const obligation = new anchor.web3.PublicKey(userObligation.obligationAddress);
+    const hfStatePda = hfStatePdaFor(obligation);
+    await program.methods
+      .computeHfFromObligation()
+      .accounts({
+        user: wallet.publicKey,
+        obligation,
+        lendingMarket,
+        hfState: hfStatePda,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(reserveAccounts)
+      .rpc();
+
+    const hfState = await program.account.hfState.fetch(hfStatePda);
+    if (!("reservePrices" in hfState.pricing)) throw new Error(`unexpected pricing ${JSON.stringify(hfState.pricing)}`);
+    const hfDecimal = convertHfQ64ToDecimal(hfState);
+    console.log(`On-chain Health Factor from obligation (Q64.64): ${hfDecimal.toFixed(4)}x`);
+  });
+
+  it("lets a keeper refresh another user's stored HF", async () => {
+    const { market: loadedMarket } = await loadReserveData({
+      rpc,
+      marketPubkey: MAIN_MARKET_ADDRESS,
+      mintPubkey: SOL_MINT_ADDRESS,
+    });
+
+    const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+    if (!userObligation) throw new Error("User has no Kamino obligation");
+
+    const reserveAccounts = getObligationReserveAccounts(userObligation);
+    const reserves = reserveAccounts.map(r => loadedMarket.getReserveByAddress(address(r.pubkey.toBase58()))!);
+    await sendAndConfirmTx({ rpc, wsRpc: ws }, signer, createRefreshInstructions(loadedMarket, reserves), [], "refresh reserves");
+
+    const keeper = Keypair.generate();
+    await airdropSol(connection, keeper.publicKey, 1);
+
+    const obligation = new anchor.web3.PublicKey(userObligation.obligationAddress);
+    await program.methods
+      .refreshHf()
+      .accounts({
+        keeper: keeper.publicKey,
+        owner: wallet.publicKey,
+        obligation,
+        lendingMarket,
+      })
+      .remainingAccounts(reserveAccounts)
+      .signers([keeper])
+      .rpc();
+
+    const hfState = await program.account.hfState.fetch(hfStatePdaFor(obligation));
+    console.log(`Keeper-refreshed Health Factor (Q64.64): ${convertHfQ64ToDecimal(hfState).toFixed(4)}x`);
+  });
+
+  it("reads HF through return data without storing it", async () => {
+    const { market: loadedMarket } = await loadReserveData({
+      rpc,
+      marketPubkey: MAIN_MARKET_ADDRESS,
+      mintPubkey: SOL_MINT_ADDRESS,
+    });
+
+    const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+    if (!userObligation) throw new Error("User has no Kamino obligation");
+
+    const reserveAccounts = getObligationReserveAccounts(userObligation);
+    const reserves = reserveAccounts.map(r => loadedMarket.getReserveByAddress(address(r.pubkey.toBase58()))!);
+    await sendAndConfirmTx({ rpc, wsRpc: ws }, signer, createRefreshInstructions(loadedMarket, reserves), [], "refresh reserves");
+
+    const tx = await program.methods
+      .getHf()
+      .accounts({ obligation: new anchor.web3.PublicKey(userObligation.obligationAddress), lendingMarket })
+      .remainingAccounts(reserveAccounts)
+      .transaction();
+    const breakdown = await simulateBreakdown(tx);
+    const hfDecimal = convertHfQ64ToDecimal({ lastHfQ64: breakdown.hfQ64 });
+    console.log(`Health Factor from return data (Q64.64): ${hfDecimal.toFixed(4)}x`);
+  });
+
+  it("asserts the obligation HF against a minimum", async () => {
+    const { market: loadedMarket } = await loadReserveData({
+      rpc,
+      marketPubkey: MAIN_MARKET_ADDRESS,
+      mintPubkey: SOL_MINT_ADDRESS,
+    });
+
+    const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+    if (!userObligation) throw new Error("User has no Kamino obligation");
+
+    const reserveAccounts = getObligationReserveAccounts(userObligation);
+    const reserves = reserveAccounts.map(r => loadedMarket.getReserveByAddress(address(r.pubkey.toBase58()))!);
+    await sendAndConfirmTx({ rpc, wsRpc: ws }, signer, createRefreshInstructions(loadedMarket, reserves), [], "refresh reserves");
+
+    const obligation = new anchor.web3.PublicKey(userObligation.obligationAddress);
+    const q64 = (hf: number) => new anchor.BN(hf).shln(64);
+
+    await program.methods.assertHealthy(q64(0)).accounts({ obligation, lendingMarket }).remainingAccounts(reserveAccounts).rpc();
+
+    let failed = false;
+    try {
+      await program.methods.assertHealthy(q64(1_000_000)).accounts({ obligation, lendingMarket }).remainingAccounts(reserveAccounts).rpc();
+    } catch (e) {
+      failed = e.toString().includes("Unhealthy");
+    }
+    if (!failed) throw new Error("assert_healthy accepted an HF below the minimum");
+  });
+
+  it("restricts config changes to the admin and hands over admin in two steps", async () => {
+    const newAdmin = Keypair.generate();
+    await program.methods.updateConfig(configParams).accounts({ admin: wallet.publicKey }).rpc();
+
+    try {
+      await program.methods.updateConfig(configParams).accounts({ admin: newAdmin.publicKey }).signers([newAdmin]).rpc();
+      throw new Error("expected update_config to fail");
+    } catch (err) {
+      if (!String(err).includes("Unauthorized")) throw err;
+    }
+
+    await program.methods.transferAdmin(newAdmin.publicKey).accounts({ admin: wallet.publicKey }).rpc();
+    await program.methods.acceptAdmin().accounts({ newAdmin: newAdmin.publicKey }).signers([newAdmin]).rpc();
+    let config = await program.account.config.fetch(configPda);
+    if (!config.admin.equals(newAdmin.publicKey) || config.pendingAdmin !== null) {
+      throw new Error("admin handover did not complete");
+    }
+
+    // hand control back so the rest of the suite keeps its admin
+    await program.methods.transferAdmin(wallet.publicKey).accounts({ admin: newAdmin.publicKey }).signers([newAdmin]).rpc();
+    await program.methods.acceptAdmin().accounts({ newAdmin: wallet.publicKey }).rpc();
+    config = await program.account.config.fetch(configPda);
+    if (!config.admin.equals(wallet.publicKey)) throw new Error("admin was not handed back");
+  });
+
+  it("rejects HF writes while paused but keeps read-only HF available", async () => {
+    const { market: loadedMarket } = await loadReserveData({
+      rpc,
+      marketPubkey: MAIN_MARKET_ADDRESS,
+      mintPubkey: SOL_MINT_ADDRESS,
+    });
+
+    const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+    if (!userObligation) throw new Error("User has no Kamino obligation");
+    const obligationKey = new anchor.web3.PublicKey(userObligation.obligationAddress);
+    const reserveAccounts = getObligationReserveAccounts(userObligation);
+    const reserves = reserveAccounts.map(r => loadedMarket.getReserveByAddress(address(r.pubkey.toBase58()))!);
+    await sendAndConfirmTx({ rpc, wsRpc: ws }, signer, createRefreshInstructions(loadedMarket, reserves), [], "refresh reserves");
+
+    await program.methods.setPaused(true).accounts({ admin: wallet.publicKey }).rpc();
+    try {
+      try {
+        await program.methods
+          .computeHfFromObligation()
+          .accounts({ user: wallet.publicKey, obligation: obligationKey, lendingMarket })
+          .remainingAccounts(reserveAccounts)
+          .rpc();
+        throw new Error("expected compute_hf_from_obligation to fail while paused");
+      } catch (err) {
+        if (!String(err).includes("Paused")) throw err;
+      }
+
+      try {
+        await program.methods
+          .computeHf({ collaterals: [], debts: [] })
+          .accounts({ user: wallet.publicKey, obligation: obligationKey, lendingMarket })
+          .rpc();
+        throw new Error("expected compute_hf to fail while paused");
+      } catch (err) {
+        if (!String(err).includes("Paused")) throw err;
+      }
+
+      await program.methods.getHf().accounts({ obligation: obligationKey, lendingMarket }).remainingAccounts(reserveAccounts).simulate();
+    } finally {
+      await program.methods.setPaused(false).accounts({ admin: wallet.publicKey }).rpc();
+    }
+  });
+
+  it("rejects obligations of markets outside the allowlist", async () => {
+    const { market: loadedMarket } = await loadReserveData({
+      rpc,
+      marketPubkey: MAIN_MARKET_ADDRESS,
+      mintPubkey: SOL_MINT_ADDRESS,
+    });
+
+    const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+    if (!userObligation) throw new Error("User has no Kamino obligation");
+    const obligation = new anchor.web3.PublicKey(userObligation.obligationAddress);
+    const reserveAccounts = getObligationReserveAccounts(userObligation);
+
+    const otherMarket = Keypair.generate().publicKey;
+    await program.methods
+      .updateConfig({ ...configParams, allowedMarkets: [otherMarket] })
+      .accounts({ admin: wallet.publicKey })
+      .rpc();
+    try {
+      await program.methods.getHf().accounts({ obligation, lendingMarket }).remainingAccounts(reserveAccounts).simulate();
+      throw new Error("expected get_hf to fail for a market outside the allowlist");
+    } catch (err) {
+      if (!String(err).includes("MarketNotAllowed")) throw err;
+    } finally {
+      await program.methods.updateConfig(configParams).accounts({ admin: wallet.publicKey }).rpc();
+    }
+  });
+
+  describe("close_hf_state / close_hf_accounts", () => {
+    const hfHistoryPdaFor = (obligation: anchor.web3.PublicKey) =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("hf_history"), lendingMarket.toBuffer(), obligation.toBuffer()],
+        program.programId
+      )[0];
+
+    const stranger = Keypair.generate();
+    before(async () => {
+      await airdropSol(connection, stranger.publicKey, 1);
+    });
+
+    // (Re)creates the test obligation's `HfState` and `HfHistory`
+    const storeHf = async () => {
+      const { market: loadedMarket } = await loadReserveData({
+        rpc,
+        marketPubkey: MAIN_MARKET_ADDRESS,
+        mintPubkey: SOL_MINT_ADDRESS,
+      });
+      const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+      if (!userObligation) throw new Error("User has no Kamino obligation");
+
+      const reserveAccounts = getObligationReserveAccounts(userObligation);
+      const reserves = reserveAccounts.map(r => loadedMarket.getReserveByAddress(address(r.pubkey.toBase58()))!);
+      await sendAndConfirmTx({ rpc, wsRpc: ws }, signer, createRefreshInstructions(loadedMarket, reserves), [], "refresh reserves");
+
+      const obligation = new anchor.web3.PublicKey(userObligation.obligationAddress);
+      await program.methods
+        .computeHfFromObligation()
+        .accounts({ user: wallet.publicKey, obligation, lendingMarket })
+        .remainingAccounts(reserveAccounts)
+        .rpc();
+      return { hfState: hfStatePdaFor(obligation), hfHistory: hfHistoryPdaFor(obligation) };
+    };
+
+    // Lamports the wallet gains from a transaction, fees included
+    const refundOf = async (send: () => Promise<string>) => {
+      const before = await connection.getBalance(wallet.publicKey, "confirmed");
+      const sig = await send();
+      const tx = await connection.getTransaction(sig, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+      const after = await connection.getBalance(wallet.publicKey, "confirmed");
+      return after - before + tx!.meta!.fee;
+    };
+
+    const expectFailure = async (send: () => Promise<unknown>, error: string) => {
+      try {
+        await send();
+      } catch (err) {
+        if (String(err).includes(error)) return;
+        throw err;
+      }
+      throw new Error(`expected the transaction to fail with ${error}`);
+    };
+
+    it("only lets the owner close an HfState and refunds its rent to them", async () => {
+      const { hfState } = await storeHf();
+      const rent = await connection.getBalance(hfState, "confirmed");
+
+      await expectFailure(
+        () => program.methods.closeHfState().accounts({ user: stranger.publicKey, hfState }).signers([stranger]).rpc(),
+        "HfStateOwnerMismatch"
+      );
+
+      const refund = await refundOf(() =>
+        program.methods.closeHfState().accounts({ user: wallet.publicKey, hfState }).rpc({ commitment: "confirmed" })
+      );
+      if (refund !== rent) throw new Error(`expected a ${rent} lamport refund, got ${refund}`);
+      if (await connection.getAccountInfo(hfState, "confirmed")) throw new Error("HfState was not closed");
+    });
+
+    it("rejects remaining accounts that are not the caller's HfHistory", async () => {
+      const { hfState, hfHistory } = await storeHf();
+      const writable = (pubkey: anchor.web3.PublicKey) => [{ pubkey, isSigner: false, isWritable: true }];
+
+      // someone else's HfHistory
+      await expectFailure(
+        () =>
+          program.methods
+            .closeHfAccounts()
+            .accounts({ user: stranger.publicKey })
+            .remainingAccounts(writable(hfHistory))
+            .signers([stranger])
+            .rpc(),
+        "HfStateOwnerMismatch"
+      );
+      // program accounts that are not an HfHistory, including HfState (closed by close_hf_state)
+      for (const account of [hfState, configPda]) {
+        await expectFailure(
+          () =>
+            program.methods
+              .closeHfAccounts()
+              .accounts({ user: wallet.publicKey })
+              .remainingAccounts(writable(account))
+              .rpc(),
+          "AccountDiscriminatorMismatch"
+        );
+      }
+      for (const account of [hfState, hfHistory]) {
+        if (!(await connection.getAccountInfo(account, "confirmed"))) throw new Error("a rejected close removed an account");
+      }
+    });
+
+    it("closes two of the owner's HfHistory accounts in one call, refunding both rents", async () => {
+      const { hfHistory } = await storeHf();
+      // the wallet has a single obligation, so the second history is a copy of the first at a fresh address
+      const original = await connection.getAccountInfo(hfHistory, "confirmed");
+      const copy = Keypair.generate().publicKey;
+      await setAccountData(connection, copy, original!.data, { owner: program.programId, lamports: original!.lamports });
+      const histories = [hfHistory, copy];
+      const rent = 2 * original!.lamports;
+
+      const refund = await refundOf(() =>
+        program.methods
+          .closeHfAccounts()
+          .accounts({ user: wallet.publicKey })
+          .remainingAccounts(histories.map(pubkey => ({ pubkey, isSigner: false, isWritable: true })))
+          .rpc({ commitment: "confirmed" })
+      );
+      if (refund !== rent) throw new Error(`expected a ${rent} lamport refund, got ${refund}`);
+      for (const account of histories) {
+        if (await connection.getAccountInfo(account, "confirmed")) throw new Error(`${account.toBase58()} was not closed`);
+      }
+    });
+  });
+
+  describe("liquidate_if_unhealthy", () => {
+    // klend `Reserve` offsets of `config.loan_to_value_pct` and `config.liquidation_threshold_pct`
+    const RESERVE_CONFIG_LOAN_TO_VALUE_PCT = 8 + 4864;
+    const RESERVE_CONFIG_LIQUIDATION_THRESHOLD_PCT = 8 + 4865;
+    const KLEND_LIQUIDATE_DISCRIMINATORS = [
+      "liquidate_obligation_and_redeem_reserve_collateral",
+      "liquidate_obligation_and_redeem_reserve_collateral_v2",
+    ].map(name => createHash("sha256").update(`global:${name}`).digest().subarray(0, 8));
+
+    // kit preflight errors carry the program logs (and thus Anchor error names) in their context
+    const errorText = (err: any) =>
+      `${err} ${JSON.stringify(err?.context ?? {}, (_, v) => (typeof v === "bigint" ? v.toString() : v))}`;
+
+    const liquidator = Keypair.generate();
+    let liquidatorSigner: Awaited<ReturnType<typeof createKeyPairSignerFromBytes>>;
+    let solReserveKey: anchor.web3.PublicKey;
+    let originalRiskParams: Buffer | undefined;
+
+    before(async () => {
+      liquidatorSigner = await createKeyPairSignerFromBytes(liquidator.secretKey);
+      await airdropSol(connection, liquidator.publicKey, 1);
+      await fundLiquidatorWithUsdc(connection, wallet, liquidator, USDC_MINT_ADDRESS, 10);
+    });
+
+    after(async () => {
+      // put back the SOL reserve's LTV and liquidation threshold for the rest of the fork
+      if (!originalRiskParams) return;
+      const data = Buffer.from((await connection.getAccountInfo(solReserveKey))!.data);
+      originalRiskParams.copy(data, RESERVE_CONFIG_LOAN_TO_VALUE_PCT);
+      await setAccountData(connection, solReserveKey, data);
+    });
+
+    // Builds the klend SDK liquidation of the test obligation, repaying USDC and seizing SOL,
+    // with klend's own liquidation instruction swapped for `liquidate_if_unhealthy`
+    const liquidateIfUnhealthyIxs = async (repayAmount: number, minCollateralReceived: number) => {
+      const { market: loadedMarket } = await loadReserveData({
+        rpc,
+        marketPubkey: MAIN_MARKET_ADDRESS,
+        mintPubkey: SOL_MINT_ADDRESS,
+      });
+      await loadedMarket.loadReserves();
+      await loadedMarket.refreshAll();
+      const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+      if (!userObligation) throw new Error("User has no Kamino obligation");
+
+      const ixs = await buildKaminoLiquidationIxs(
+        loadedMarket,
+        repayAmount,
+        minCollateralReceived,
+        loadedMarket.getReserveByMint(USDC_MINT_ADDRESS)!,
+        loadedMarket.getReserveByMint(SOL_MINT_ADDRESS)!,
+        liquidatorSigner,
+        signer.address
+      );
+      const index = ixs.findIndex(ix =>
+        ix.programAddress === loadedMarket.programId &&
+        KLEND_LIQUIDATE_DISCRIMINATORS.some(d => Buffer.from(ix.data!).subarray(0, 8).equals(d))
+      );
+      if (index < 0) throw new Error("klend SDK did not build a liquidation instruction");
+
+      // klend's v1 and v2 liquidations start with the same 20 accounts, in `LiquidateIfUnhealthy` order
+      const [
+        liquidatorKey, obligation, market, lendingMarketAuthority,
+        repayReserve, repayReserveLiquidityMint, repayReserveLiquiditySupply,
+        withdrawReserve, withdrawReserveLiquidityMint, withdrawReserveCollateralMint,
+        withdrawReserveCollateralSupply, withdrawReserveLiquiditySupply, withdrawReserveLiquidityFeeReceiver,
+        userSourceLiquidity, userDestinationCollateral, userDestinationLiquidity,
+        collateralTokenProgram, repayLiquidityTokenProgram, withdrawLiquidityTokenProgram, instructionSysvarAccount,
+      ] = ixs[index].accounts!.slice(0, 20).map(a => new anchor.web3.PublicKey(a.address));
+      const ix = await program.methods
+        .liquidateIfUnhealthy(new anchor.BN(repayAmount), new anchor.BN(minCollateralReceived))
+        .accountsPartial({
+          liquidator: liquidatorKey,
+          obligation,
+          lendingMarket: market,
+          lendingMarketAuthority,
+          repayReserve,
+          repayReserveLiquidityMint,
+          repayReserveLiquiditySupply,
+          withdrawReserve,
+          withdrawReserveLiquidityMint,
+          withdrawReserveCollateralMint,
+          withdrawReserveCollateralSupply,
+          withdrawReserveLiquiditySupply,
+          withdrawReserveLiquidityFeeReceiver,
+          userSourceLiquidity,
+          userDestinationCollateral,
+          userDestinationLiquidity,
+          collateralTokenProgram,
+          repayLiquidityTokenProgram,
+          withdrawLiquidityTokenProgram,
+          instructionSysvarAccount,
+          klendProgram: KLEND_PROGRAM_ID,
+          config: configPda,
+        })
+        .remainingAccounts(getObligationReserveAccounts(userObligation))
+        .instruction();
+
+      ixs[index] = toKitInstruction(ix);
+      return { ixs, obligation: userObligation };
+    };
+
+    const usdcDebtSf = async () => {
+      const { market: loadedMarket } = await loadReserveData({
+        rpc,
+        marketPubkey: MAIN_MARKET_ADDRESS,
+        mintPubkey: SOL_MINT_ADDRESS,
+      });
+      const userObligation = await loadedMarket.getUserVanillaObligation(signer.address);
+      const usdcReserve = loadedMarket.getReserveByMint(USDC_MINT_ADDRESS)!.address;
+      const borrow = userObligation!.state.borrows.find(b => b.borrowReserve === usdcReserve)!;
+      return new anchor.BN(borrow.borrowedAmountSf.toString());
+    };
+
+    it("refuses to liquidate a healthy obligation", async () => {
+      const { ixs } = await liquidateIfUnhealthyIxs(5_000_000, 1);
+      try {
+        await sendAndConfirmTx({ rpc, wsRpc: ws }, liquidatorSigner, ixs, [], "liquidate healthy obligation");
+        throw new Error("expected liquidate_if_unhealthy to fail for a healthy obligation");
+      } catch (err) {
+        if (!errorText(err).includes("ObligationHealthy")) throw err;
+      }
+    });
+
+    it("reverts when less collateral than the minimum is received", async () => {
+      // Drop the SOL reserve's liquidation threshold to 1% so 1 SOL no longer covers 50 USDC of debt
+      const { market: loadedMarket } = await loadReserveData({
+        rpc,
+        marketPubkey: MAIN_MARKET_ADDRESS,
+        mintPubkey: SOL_MINT_ADDRESS,
+      });
+      solReserveKey = new anchor.web3.PublicKey(loadedMarket.getReserveByMint(SOL_MINT_ADDRESS)!.address);
+      const data = Buffer.from((await connection.getAccountInfo(solReserveKey))!.data);
+      originalRiskParams = Buffer.from(data.subarray(RESERVE_CONFIG_LOAN_TO_VALUE_PCT, RESERVE_CONFIG_LIQUIDATION_THRESHOLD_PCT + 1));
+      data[RESERVE_CONFIG_LOAN_TO_VALUE_PCT] = 0;
+      data[RESERVE_CONFIG_LIQUIDATION_THRESHOLD_PCT] = 1;
+      await setAccountData(connection, solReserveKey, data);
+
+      const debtBefore = await usdcDebtSf();
+      // 5 USDC can never seize 10 SOL
+      const { ixs } = await liquidateIfUnhealthyIxs(5_000_000, 10_000_000_000);
+      try {
+        await sendAndConfirmTx({ rpc, wsRpc: ws }, liquidatorSigner, ixs, [], "liquidate below min out");
+        throw new Error("expected liquidate_if_unhealthy to fail below the minimum collateral received");
+      } catch (err) {
+        if (String(err).includes("expected liquidate_if_unhealthy") || errorText(err).includes("ObligationHealthy")) throw err;
+      }
+      if (!(await usdcDebtSf()).eq(debtBefore)) throw new Error("a reverted liquidation changed the debt");
+    });
+
+    it("liquidates an unhealthy obligation through klend", async () => {
+      const debtBefore = await usdcDebtSf();
+      const { ixs } = await liquidateIfUnhealthyIxs(5_000_000, 1);
+      await sendAndConfirmTx({ rpc, wsRpc: ws }, liquidatorSigner, ixs, [], "liquidate unhealthy obligation");
+
+      const debtAfter = await usdcDebtSf();
+      if (!debtAfter.lt(debtBefore)) throw new Error("liquidation did not repay any debt");
+      console.log(`Liquidated: USDC debt ${debtBefore.shrn(60).toString()} -> ${debtAfter.shrn(60).toString()}`);
+    });
+  });
 });
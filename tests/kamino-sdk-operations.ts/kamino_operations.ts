@@ -161,6 +161,41 @@ export async function executeKaminoLiquidation(
     obligationOwner: Address,
     rpc: Rpc<SolanaRpcApi>,
     wsRpc: RpcSubscriptions<SignatureNotificationsApi & SlotNotificationsApi>,
+) {
+    const liquidationIxs = await buildKaminoLiquidationIxs(
+        loadedMarket,
+        repayAmount,
+        minCollateralReceiveAmount,
+        usdcReserve,
+        solReserve,
+        liquidator,
+        obligationOwner
+    );
+
+    await sendAndConfirmTx({ rpc, wsRpc }, liquidator, liquidationIxs, [], "liquidation");
+}
+
+/**
+ * Builds every instruction of a Kamino liquidation without sending it:
+ * compute budget, setup (token accounts), reserve/obligation refreshes, the liquidation itself and cleanup.
+ *
+ * @param loadedMarket - Loaded {@link KaminoMarket} instance.
+ * @param repayAmount - Amount of debt token (e.g. USDC) to repay (in smallest units).
+ * @param minCollateralReceiveAmount - Minimum acceptable amount of collateral to receive (e.g. in lamports).
+ * @param usdcReserve - Debt reserve (the asset being repaid).
+ * @param solReserve - Collateral reserve (the asset being seized).
+ * @param liquidator - Liquidator signer performing the liquidation.
+ * @param obligationOwner - Address of the borrower being liquidated.
+ * @returns The liquidation instructions, in transaction order.
+ */
+export async function buildKaminoLiquidationIxs(
+    loadedMarket: KaminoMarket,
+    repayAmount: number,
+    minCollateralReceiveAmount: number,
+    usdcReserve: KaminoReserve,
+    solReserve: KaminoReserve,
+    liquidator: KeyPairSigner,
+    obligationOwner: Address,
 ) {
     const obligation = await loadedMarket.getUserVanillaObligation(obligationOwner);
 
@@ -184,18 +219,12 @@ export async function executeKaminoLiquidation(
         BigInt(0)                              
     );
 
-    await sendAndConfirmTx(
-        { rpc, wsRpc },
-        liquidator,
-        [
-            ...liquidationAction.computeBudgetIxs,
-            ...liquidationAction.setupIxs,
-            ...liquidationAction.lendingIxs,
-            ...liquidationAction.cleanupIxs,
-        ],
-        [],
-        "liquidation"
-    );
+    return [
+        ...liquidationAction.computeBudgetIxs,
+        ...liquidationAction.setupIxs,
+        ...liquidationAction.lendingIxs,
+        ...liquidationAction.cleanupIxs,
+    ];
 }
 
 /**
@@ -1,6 +1,6 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Connection, PublicKey } from "@solana/web3.js";
-import { KaminoMarket, DEFAULT_RECENT_SLOT_DURATION_MS, KaminoMarketRpcApi } from "@kamino-finance/klend-sdk";
+import { KaminoMarket, KaminoObligation, DEFAULT_RECENT_SLOT_DURATION_MS, KaminoMarketRpcApi, isNotNullPubkey } from "@kamino-finance/klend-sdk";
 import axios from "axios";
 import {
   createDefaultRpcTransport,
@@ -28,6 +28,8 @@ import {
   SlotNotificationsApi,
   createSolanaRpcSubscriptions,
   Address,
+  AccountRole,
+  address,
 } from '@solana/kit';
 import {
   getOrCreateAssociatedTokenAccount,
@@ -338,6 +340,58 @@ export async function fundLiquidatorWithUsdc(
   console.log(`Transferred ${amount} USDC to liquidator (${liquidatorUsdcAta.address.toBase58()})`);
 }
 
+/* -------------------------------------------------------------------------- */
+/*                       LOCAL FORK & INSTRUCTION HELPERS                     */
+/* -------------------------------------------------------------------------- */
+
+/**
+ * Overwrites (or creates) an account's data on the local surfpool fork (`surfnet_setAccount` cheatcode).
+ *
+ * @param connection - Connection to the local surfpool validator.
+ * @param account - Account to overwrite.
+ * @param data - New account data.
+ * @param fields - Optional owner and lamports; left unchanged when omitted.
+ */
+export async function setAccountData(
+  connection: Connection,
+  account: PublicKey,
+  data: Buffer,
+  fields: { owner?: PublicKey; lamports?: number } = {}
+) {
+  const response = await axios.post(connection.rpcEndpoint, {
+    jsonrpc: "2.0",
+    id: 1,
+    method: "surfnet_setAccount",
+    params: [
+      account.toBase58(),
+      { data: data.toString("hex"), owner: fields.owner?.toBase58(), lamports: fields.lamports },
+    ],
+  });
+  if (response.data.error) {
+    throw new Error(`surfnet_setAccount failed: ${JSON.stringify(response.data.error)}`);
+  }
+}
+
+/**
+ * Converts a web3.js instruction (e.g. built by Anchor) to a `@solana/kit` one,
+ * so it can be sent next to klend SDK instructions with {@link sendAndConfirmTx}.
+ *
+ * @param ix - web3.js instruction.
+ * @returns The same instruction as a kit {@link Instruction}.
+ */
+export function toKitInstruction(ix: anchor.web3.TransactionInstruction): Instruction {
+  return {
+    programAddress: address(ix.programId.toBase58()),
+    accounts: ix.keys.map((key) => ({
+      address: address(key.pubkey.toBase58()),
+      role: key.isSigner
+        ? (key.isWritable ? AccountRole.WRITABLE_SIGNER : AccountRole.READONLY_SIGNER)
+        : (key.isWritable ? AccountRole.WRITABLE : AccountRole.READONLY),
+    })),
+    data: new Uint8Array(ix.data),
+  };
+}
+
 /* -------------------------------------------------------------------------- */
 /*                         DATA & COMPUTATION HELPERS                         */
 /* -------------------------------------------------------------------------- */
@@ -407,16 +461,45 @@ export async function extractAssetFromObligation(
   };
 
   if (isCollateral) {
-    const ltv = new BN(reserveState.config.loanToValuePct.toString());
-    const liqThresholdBps = ltv.lte(new BN(100)) ? ltv.muln(100) : ltv;
+    // Max LTV caps new borrows; the liquidation threshold decides liquidation eligibility
+    const maxLtvBps = Number(reserveState.config.loanToValuePct.toString()) * 100;
+    const liqThresholdBps = Number(reserveState.config.liquidationThresholdPct.toString()) * 100;
     return {
       ...baseData,
+      maxLtvBps,
       liqThresholdBps,
       borrowFactorBps: new BN(0),
     };
   }
 
-  return baseData;
+  // Borrow factors scale debt up, like klend's borrow factor adjusted debt value
+  const borrowFactorBps = Number(reserveState.config.borrowFactorPct.toString()) * 100; // u32
+  return {
+    ...baseData,
+    borrowFactorBps,
+  };
+}
+
+/**
+ * Builds the remaining accounts expected by `compute_hf_from_obligation`:
+ * the obligation's deposit reserves followed by its borrow reserves, in slot order.
+ *
+ * @param obligation - Loaded {@link KaminoObligation}.
+ * @returns Read-only account metas for the obligation's reserves.
+ */
+export function getObligationReserveAccounts(obligation: KaminoObligation) {
+  const depositReserves = obligation.state.deposits
+    .map((d) => d.depositReserve)
+    .filter((r) => isNotNullPubkey(r));
+  const borrowReserves = obligation.state.borrows
+    .map((b) => b.borrowReserve)
+    .filter((r) => isNotNullPubkey(r));
+
+  return [...depositReserves, ...borrowReserves].map((reserve) => ({
+    pubkey: new PublicKey(reserve),
+    isSigner: false,
+    isWritable: false,
+  }));
 }
 
 /**